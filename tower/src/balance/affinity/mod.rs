@@ -0,0 +1,43 @@
+//! This module implements a load balancer with request affinity ("sticky sessions").
+//!
+//! Some protocols keep state on the endpoint that served a request, so that follow-up requests
+//! must be sent to the same endpoint. The balancer in this module reports the key of the endpoint
+//! that served each request by wrapping its response in [`Served`], and extracts an optional
+//! _affinity key_ from each request using [`AffinityKey`]. When a request carries an affinity key
+//! and the endpoint with that key is ready, the request is sent to it. Otherwise, for instance
+//! because the endpoint has gone away, the request is sent to an endpoint chosen by
+//! [`p2c`](crate::balance::p2c), and the new endpoint's key is reported in its response.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use tower::balance::affinity::Balance;
+//! use tower::discover::Discover;
+//! use tower::Service;
+//!
+//! struct Request {
+//!     session: Option<usize>,
+//!     body: String,
+//! }
+//!
+//! // Follow-up requests carry the key that was reported with the first response.
+//! fn session(req: &Request) -> Option<usize> {
+//!     req.session
+//! }
+//!
+//! fn sticky<D>(discover: D) -> Balance<D, fn(&Request) -> Option<usize>, Request>
+//! where
+//!     D: Discover<Key = usize>,
+//!     D::Service: Service<Request>,
+//!     <D::Service as Service<Request>>::Error: Into<tower::BoxError>,
+//! {
+//!     Balance::new(discover, session)
+//! }
+//! ```
+
+mod service;
+
+#[cfg(test)]
+mod test;
+
+pub use service::{AffinityKey, Balance, ResponseFuture, Served};
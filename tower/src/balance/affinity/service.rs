@@ -0,0 +1,214 @@
+use super::super::p2c;
+use crate::discover::Discover;
+use crate::load::Load;
+use futures_core::ready;
+use pin_project_lite::pin_project;
+use std::hash::Hash;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+use tracing::trace;
+
+/// Extracts the affinity key that a request carries, if any.
+///
+/// See the [module-level documentation](..) for details.
+pub trait AffinityKey<Req, K> {
+    /// Returns the key of the endpoint that `req` should be sent to, if it has one.
+    fn affinity_key(&mut self, req: &Req) -> Option<K>;
+}
+
+impl<F, Req, K> AffinityKey<Req, K> for F
+where
+    F: FnMut(&Req) -> Option<K>,
+{
+    fn affinity_key(&mut self, req: &Req) -> Option<K> {
+        self(req)
+    }
+}
+
+/// A response, along with the key of the endpoint that served it.
+#[derive(Clone, Debug)]
+pub struct Served<K, T> {
+    key: K,
+    response: T,
+}
+
+/// Distributes requests across services, honoring the affinity key that each request carries.
+///
+/// See the [module-level documentation](..) for details.
+///
+/// Note that [`Balance`] requires that the [`Discover`] you use is [`Unpin`] in order to implement
+/// [`Service`]. This is because it needs to be accessed from [`Service::poll_ready`], which takes
+/// `&mut self`. You can achieve this easily by wrapping your [`Discover`] in [`Box::pin`] before you
+/// construct the [`Balance`] instance. For more details, see [#319].
+///
+/// [`Box::pin`]: std::boxed::Box::pin()
+/// [#319]: https://github.com/tower-rs/tower/issues/319
+pub struct Balance<D, F, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+{
+    inner: p2c::Balance<D, Req>,
+    affinity_key: F,
+}
+
+pin_project! {
+    /// Response future for [`Balance`].
+    #[derive(Debug)]
+    pub struct ResponseFuture<K, F> {
+        #[pin]
+        inner: F,
+        key: Option<K>,
+    }
+}
+
+impl<D: Discover, F, Req> fmt::Debug for Balance<D, F, Req>
+where
+    D: fmt::Debug,
+    D::Key: Hash + fmt::Debug,
+    D::Service: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<D, F, Req> Balance<D, F, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Constructs a load balancer that extracts the affinity key of each request with
+    /// `affinity_key`.
+    pub fn new(discover: D, affinity_key: F) -> Self {
+        Self::from_balance(p2c::Balance::new(discover), affinity_key)
+    }
+
+    /// Constructs a load balancer that falls back to `balance` for requests without an affinity
+    /// key, or whose endpoint is not ready.
+    ///
+    /// This can be used to configure the fallback balancer, for instance with a
+    /// [`DiscoverPolicy`](p2c::DiscoverPolicy).
+    pub fn from_balance(balance: p2c::Balance<D, Req>, affinity_key: F) -> Self {
+        Self {
+            inner: balance,
+            affinity_key,
+        }
+    }
+
+    /// Returns the number of endpoints currently tracked by the balancer.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Returns whether or not the balancer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+}
+
+impl<D, F, Req> Service<Req> for Balance<D, F, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req> + Load,
+    <D::Service as Load>::Metric: std::fmt::Debug,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    F: AffinityKey<Req, D::Key>,
+{
+    type Response = Served<D::Key, <D::Service as Service<Req>>::Response>;
+    type Error = crate::BoxError;
+    type Future = ResponseFuture<D::Key, <p2c::Balance<D, Req> as Service<Req>>::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // A fallback endpoint must be ready in case the request's endpoint is not.
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        let request = match self.affinity_key.affinity_key(&request) {
+            Some(key) => match self.inner.call_key(&key, request) {
+                Ok(inner) => {
+                    trace!("sending request to its affinity endpoint");
+                    return ResponseFuture {
+                        inner,
+                        key: Some(key),
+                    };
+                }
+                Err(request) => {
+                    trace!("affinity endpoint is not ready");
+                    request
+                }
+            },
+            None => request,
+        };
+
+        let key = self
+            .inner
+            .selected_key()
+            .expect("called before ready")
+            .clone();
+        ResponseFuture {
+            inner: self.inner.call(request),
+            key: Some(key),
+        }
+    }
+}
+
+// === impl Served ===
+
+impl<K, T> Served<K, T> {
+    /// Returns the key of the endpoint that served the response.
+    ///
+    /// Follow-up requests that should be sent to the same endpoint should carry this key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns a reference to the response.
+    pub fn get_ref(&self) -> &T {
+        &self.response
+    }
+
+    /// Returns a mutable reference to the response.
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.response
+    }
+
+    /// Consumes `self`, returning the response.
+    pub fn into_inner(self) -> T {
+        self.response
+    }
+
+    /// Consumes `self`, returning the key of the endpoint that served the response and the
+    /// response.
+    pub fn into_parts(self) -> (K, T) {
+        (self.key, self.response)
+    }
+}
+
+// === impl ResponseFuture ===
+
+impl<K, F, T, E> Future for ResponseFuture<K, F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<Served<K, T>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let response = ready!(this.inner.poll(cx))?;
+        let key = this.key.take().expect("polled after completion");
+        Poll::Ready(Ok(Served { key, response }))
+    }
+}
@@ -0,0 +1,82 @@
+use crate::discover::ServiceList;
+use crate::load;
+use futures_util::pin_mut;
+use std::task::Poll;
+use tokio_test::{assert_ready_ok, task};
+use tower_test::{assert_request_eq, mock};
+
+use super::*;
+
+type Req = Option<usize>;
+
+fn by_request(req: &Req) -> Option<usize> {
+    *req
+}
+
+#[tokio::test]
+async fn follows_affinity_key() {
+    let (mock_a, handle_a) = mock::pair::<Req, &'static str>();
+    let (mock_b, handle_b) = mock::pair::<Req, &'static str>();
+    let mock_a = load::Constant::new(mock_a, 0);
+    let mock_b = load::Constant::new(mock_b, 0);
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request));
+
+    handle_a.allow(1);
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+
+    // A request without an affinity key is sent to some endpoint, which is reported along with
+    // its response.
+    let mut fut = task::spawn(svc.call(None));
+    if let Poll::Ready(Some((_, rsp))) = handle_a.as_mut().poll_request() {
+        rsp.send_response("a");
+    } else {
+        assert_request_eq!(handle_b, None).send_response("b");
+    }
+    let served = assert_ready_ok!(fut.poll());
+    let key = *served.key();
+    assert_eq!(served.into_inner(), ["a", "b"][key]);
+
+    // Follow-up requests are sent to the same endpoint.
+    for _ in 0..4 {
+        handle_a.allow(1);
+        handle_b.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+
+        let mut fut = task::spawn(svc.call(Some(key)));
+        if key == 0 {
+            assert_request_eq!(handle_a, Some(0)).send_response("a");
+        } else {
+            assert_request_eq!(handle_b, Some(1)).send_response("b");
+        }
+        assert_eq!(*assert_ready_ok!(fut.poll()).key(), key);
+    }
+}
+
+#[tokio::test]
+async fn falls_back_when_not_ready() {
+    let (mock_a, handle_a) = mock::pair::<Req, &'static str>();
+    let (mock_b, handle_b) = mock::pair::<Req, &'static str>();
+    let mock_a = load::Constant::new(mock_a, 0);
+    let mock_b = load::Constant::new(mock_b, 0);
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request));
+
+    handle_a.allow(0);
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+
+    // The request's endpoint isn't ready, so it's sent elsewhere, and the new endpoint is
+    // reported.
+    let mut fut = task::spawn(svc.call(Some(0)));
+    assert_request_eq!(handle_b, Some(0)).send_response("b");
+    let served = assert_ready_ok!(fut.poll());
+    assert_eq!(served.into_parts(), (1, "b"));
+}
@@ -0,0 +1,43 @@
+//! [`Future`] types
+//!
+//! [`Future`]: std::future::Future
+use futures_core::ready;
+use pin_project_lite::pin_project;
+use std::sync::Arc;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Future for the [`Balance`] service.
+    ///
+    /// [`Balance`]: crate::balance::hash::Balance
+    #[derive(Debug)]
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        // Keep these around so that the request counts as in flight, both for its endpoint and
+        // in total, until the future completes.
+        _in_flight: Option<(Arc<()>, Arc<()>)>,
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    pub(super) fn new(inner: F, _in_flight: Option<(Arc<()>, Arc<()>)>) -> Self {
+        ResponseFuture { inner, _in_flight }
+    }
+}
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<crate::BoxError>,
+{
+    type Output = Result<T, crate::BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(ready!(self.project().inner.poll(cx)).map_err(Into::into))
+    }
+}
@@ -0,0 +1,58 @@
+//! This module implements a consistent-hashing load balancer.
+//!
+//! Unlike [`p2c`](crate::balance::p2c), which picks an endpoint without regard for the request
+//! being sent, the balancer in this module extracts a key from each request and hashes it onto a
+//! [ring] of endpoints. Requests with the same key are sent to the same endpoint for as long as
+//! that endpoint remains in the service set, which makes this balancer a good fit for workloads
+//! that benefit from cache affinity.
+//!
+//! Each endpoint is placed on the ring at a number of pseudo-random points derived from its
+//! [`Discover::Key`](crate::discover::Discover::Key). When an endpoint is inserted or removed,
+//! only the keys that hash onto the ring segments adjacent to its points move to another endpoint;
+//! all other keys keep their assignment.
+//!
+//! If the endpoint that a request hashes to is not ready, the request is sent to the next ready
+//! endpoint along the ring instead. Since all balancers that observe the same endpoint set build
+//! the same ring, they also agree on this fallback.
+//!
+//! Popular keys can overload the endpoint they hash to. To guard against this, the load on each
+//! endpoint can be bounded with [`Balance::with_load_bound`], so that requests to an endpoint with
+//! too many requests in flight are also sent to the next endpoint along the ring.
+//!
+//! The key is extracted from each request with a [`RequestKey`], which is implemented for all
+//! closures of the form `FnMut(&Req) -> K` where `K: Hash`.
+//!
+//! [ring]: https://en.wikipedia.org/wiki/Consistent_hashing
+//!
+//! # Examples
+//!
+//! ```rust
+//! use tower::balance::hash::Balance;
+//! use tower::discover::ServiceList;
+//! use tower::Service;
+//!
+//! struct Request {
+//!     user_id: u64,
+//! }
+//!
+//! fn by_user<S>(svc1: S, svc2: S) -> impl Service<Request>
+//! where
+//!     S: Service<Request>,
+//!     S::Error: Into<tower::BoxError>,
+//! {
+//!     // All requests for the same user go to the same service.
+//!     Balance::new(
+//!         ServiceList::new(vec![svc1, svc2]),
+//!         |req: &Request| req.user_id,
+//!     )
+//! }
+//! ```
+
+pub mod future;
+mod ring;
+mod service;
+
+#[cfg(test)]
+mod test;
+
+pub use service::{Balance, RequestKey};
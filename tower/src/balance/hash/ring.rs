@@ -0,0 +1,70 @@
+use crate::hash::stable_hash;
+use std::collections::BTreeMap;
+use std::hash::Hash;
+
+/// A hash ring mapping `u64` hashes onto `K`-typed endpoint keys.
+///
+/// Every key is placed on the ring at `replicas` points. A hash is mapped onto the key at the
+/// first point that is greater than or equal to it, wrapping around at the end of the ring.
+#[derive(Debug)]
+pub(super) struct Ring<K> {
+    points: BTreeMap<u64, K>,
+    replicas: usize,
+}
+
+impl<K> Ring<K>
+where
+    K: Hash + Eq + Clone,
+{
+    pub(super) fn new(replicas: usize) -> Self {
+        debug_assert!(replicas > 0, "replicas must be positive");
+        Self {
+            points: BTreeMap::new(),
+            replicas,
+        }
+    }
+
+    /// Places `key` on the ring.
+    ///
+    /// Inserting a key that is already on the ring has no effect.
+    pub(super) fn insert(&mut self, key: &K) {
+        for replica in 0..self.replicas {
+            // On the off chance that two keys hash to the same point, the key that was inserted
+            // first keeps it.
+            self.points
+                .entry(point(key, replica))
+                .or_insert_with(|| key.clone());
+        }
+    }
+
+    /// Removes all of `key`'s points from the ring.
+    pub(super) fn remove(&mut self, key: &K) {
+        for replica in 0..self.replicas {
+            let point = point(key, replica);
+            if self.points.get(&point) == Some(key) {
+                self.points.remove(&point);
+            }
+        }
+    }
+
+    /// Returns the keys on the ring in order, starting at the point that `hash` maps to.
+    ///
+    /// Keys are yielded once for each of their points, so a key may be yielded more than once.
+    pub(super) fn iter_from(&self, hash: u64) -> impl Iterator<Item = &K> {
+        self.points
+            .range(hash..)
+            .chain(self.points.range(..hash))
+            .map(|(_, key)| key)
+    }
+}
+
+/// Hashes a value onto the ring.
+///
+/// Hashes are consistent across all balancers built from the same version of the standard library.
+pub(super) fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    stable_hash(value)
+}
+
+fn point<K: Hash>(key: &K, replica: usize) -> u64 {
+    hash(&(key, replica))
+}
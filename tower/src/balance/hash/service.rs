@@ -0,0 +1,363 @@
+use super::super::error;
+use super::future::ResponseFuture;
+use super::ring::{self, Ring};
+use crate::discover::{Change, Discover};
+use crate::ready_cache::{error::Failed, ReadyCache};
+use futures_core::ready;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// The number of points at which each endpoint is placed on the ring by [`Balance::new`].
+const DEFAULT_REPLICAS: usize = 100;
+
+/// Extracts the key that a request is hashed by.
+///
+/// See the [module-level documentation](..) for details.
+pub trait RequestKey<Req> {
+    /// The type of the extracted key.
+    type Key: Hash;
+
+    /// Returns the key that `req` should be hashed by.
+    fn request_key(&mut self, req: &Req) -> Self::Key;
+}
+
+impl<F, K, Req> RequestKey<Req> for F
+where
+    F: FnMut(&Req) -> K,
+    K: Hash,
+{
+    type Key = K;
+
+    fn request_key(&mut self, req: &Req) -> K {
+        self(req)
+    }
+}
+
+/// Distributes requests across services by consistently hashing a key extracted from each
+/// request.
+///
+/// See the [module-level documentation](..) for details.
+///
+/// Note that [`Balance`] requires that the [`Discover`] you use is [`Unpin`] in order to implement
+/// [`Service`]. This is because it needs to be accessed from [`Service::poll_ready`], which takes
+/// `&mut self`. You can achieve this easily by wrapping your [`Discover`] in [`Box::pin`] before you
+/// construct the [`Balance`] instance. For more details, see [#319].
+///
+/// [`Box::pin`]: std::boxed::Box::pin()
+/// [#319]: https://github.com/tower-rs/tower/issues/319
+pub struct Balance<D, F, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+{
+    discover: D,
+
+    services: ReadyCache<D::Key, D::Service, Req>,
+    ring: Ring<D::Key>,
+
+    request_key: F,
+
+    load_bound: Option<LoadBound<D::Key>>,
+
+    _req: PhantomData<Req>,
+}
+
+/// Tracks in-flight requests, to bound the load on each endpoint.
+struct LoadBound<K> {
+    factor: f64,
+    // Each response future holds a clone of its endpoint's count and of the total, so the number
+    // of requests in flight is the strong count of each, minus one.
+    in_flight: HashMap<K, Arc<()>>,
+    total: Arc<()>,
+}
+
+impl<D: Discover, F, Req> fmt::Debug for Balance<D, F, Req>
+where
+    D: fmt::Debug,
+    D::Key: Hash + fmt::Debug,
+    D::Service: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("discover", &self.discover)
+            .field("services", &self.services)
+            .finish()
+    }
+}
+
+impl<D, F, Req> Balance<D, F, Req>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Constructs a load balancer that hashes the key returned by `request_key` for each request.
+    pub fn new(discover: D, request_key: F) -> Self {
+        Self::with_replicas(discover, request_key, DEFAULT_REPLICAS)
+    }
+
+    /// Constructs a load balancer that places each endpoint on the ring at `replicas` points.
+    ///
+    /// More points spread keys more evenly across endpoints, at the cost of memory and of time
+    /// spent updating the ring when the endpoint set changes.
+    ///
+    /// # Panics
+    ///
+    /// If `replicas` is zero.
+    pub fn with_replicas(discover: D, request_key: F, replicas: usize) -> Self {
+        assert!(replicas > 0, "replicas must be positive");
+        Self {
+            discover,
+            services: ReadyCache::default(),
+            ring: Ring::new(replicas),
+            request_key,
+
+            load_bound: None,
+
+            _req: PhantomData,
+        }
+    }
+
+    /// Bounds the load on each endpoint to `factor` times the average number of in-flight
+    /// requests per endpoint, rounded up.
+    ///
+    /// A request whose endpoint is at its bound is sent to the next ready endpoint along the ring
+    /// that is below it, as in [consistent hashing with bounded loads]. Smaller factors spread
+    /// load more evenly, at the cost of moving more keys away from their endpoints.
+    ///
+    /// # Panics
+    ///
+    /// If `factor` is less than 1.0.
+    ///
+    /// [consistent hashing with bounded loads]: https://arxiv.org/abs/1608.01350
+    pub fn with_load_bound(mut self, factor: f64) -> Self {
+        assert!(factor >= 1.0, "load bound factor must be at least 1.0");
+        self.load_bound = Some(LoadBound {
+            factor,
+            in_flight: HashMap::new(),
+            total: Arc::new(()),
+        });
+        self
+    }
+
+    /// Returns the number of endpoints currently tracked by the balancer.
+    pub fn len(&self) -> usize {
+        self.services.len()
+    }
+
+    /// Returns whether or not the balancer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty()
+    }
+}
+
+impl<D, F, Req> Balance<D, F, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Polls `discover` for updates, adding new items to the ring and to `not_ready`.
+    fn update_pending_from_discover(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(), error::Discover>>> {
+        debug!("updating from discover");
+        loop {
+            match ready!(Pin::new(&mut self.discover).poll_discover(cx))
+                .transpose()
+                .map_err(|e| error::Discover(e.into()))?
+            {
+                None => return Poll::Ready(None),
+                Some(Change::Remove(key)) => {
+                    trace!("remove");
+                    self.ring.remove(&key);
+                    self.services.evict(&key);
+                    self.forget_load();
+                }
+                Some(Change::Insert(key, svc)) => {
+                    trace!("insert");
+                    // If this service already existed in the set, it will be
+                    // replaced as the new one becomes ready. It keeps its place
+                    // on the ring.
+                    self.ring.insert(&key);
+                    self.services.push(key, svc);
+                }
+            }
+        }
+    }
+
+    /// Stops tracking the in-flight requests to endpoints that have been removed, once none of
+    /// their requests are outstanding.
+    ///
+    /// Requests that are still in flight keep counting towards their endpoint, so that its load
+    /// isn't underestimated if it is inserted again before they complete.
+    fn forget_load(&mut self) {
+        if let Some(bound) = self.load_bound.as_mut() {
+            let services = &self.services;
+            bound.in_flight.retain(|key, count| {
+                Arc::strong_count(count) > 1
+                    || services.get_ready(key).is_some()
+                    || services.pending_contains(key)
+            });
+        }
+    }
+
+    /// Re-checks the readiness of every ready service, since the next request may be sent to any
+    /// of them, moving services that are no longer ready back to the pending set.
+    fn check_ready_services(&mut self, cx: &mut Context<'_>) {
+        // A service that is no longer ready is swapped with the last ready service, which has
+        // already been checked, so the ready set is checked from the end.
+        for index in (0..self.services.ready_len()).rev() {
+            if let Err(Failed(key, error)) = self.services.check_ready_index(cx, index) {
+                debug!(%error, "dropping failed endpoint");
+                // If the failed service is being replaced, the new service keeps its place on
+                // the ring.
+                if !self.services.pending_contains(&key) {
+                    self.ring.remove(&key);
+                    self.forget_load();
+                }
+            }
+        }
+    }
+
+    fn promote_pending_to_ready(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.services.poll_pending(cx) {
+                Poll::Ready(Ok(())) => {
+                    // There are no remaining pending services.
+                    debug_assert_eq!(self.services.pending_len(), 0);
+                    break;
+                }
+                Poll::Pending => {
+                    // None of the pending services are ready.
+                    debug_assert!(self.services.pending_len() > 0);
+                    break;
+                }
+                Poll::Ready(Err(Failed(key, error))) => {
+                    // An individual service was lost; continue processing
+                    // pending services.
+                    debug!(%error, "dropping failed endpoint");
+                    // If the failed service was replacing a ready one, the
+                    // ready service is still in the cache and keeps its place
+                    // on the ring.
+                    if self.services.get_ready(&key).is_none() {
+                        self.ring.remove(&key);
+                        self.forget_load();
+                    }
+                }
+            }
+        }
+        trace!(
+            ready = %self.services.ready_len(),
+            pending = %self.services.pending_len(),
+            "poll_unready"
+        );
+    }
+}
+
+impl<D, F, Req> Service<Req> for Balance<D, F, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    F: RequestKey<Req>,
+{
+    type Response = <D::Service as Service<Req>>::Response;
+    type Error = crate::BoxError;
+    type Future = ResponseFuture<<D::Service as Service<Req>>::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let _ = self.update_pending_from_discover(cx)?;
+        self.check_ready_services(cx);
+        self.promote_pending_to_ready(cx);
+
+        // The endpoint that a request hashes to can't be known until the request
+        // is passed to `call`, so the balancer is ready as long as any endpoint
+        // is, and every ready endpoint has been checked above. If the endpoint
+        // that the request hashes to is not ready, the request falls back to
+        // the next ready endpoint on the ring.
+        if self.services.ready_len() == 0 {
+            // We have previously registered interest in updates from discover
+            // and pending services.
+            return Poll::Pending;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        let hash = ring::hash(&self.request_key.request_key(&request));
+
+        let services = &self.services;
+        let mut ready = self
+            .ring
+            .iter_from(hash)
+            .filter(|key| services.get_ready(*key).is_some());
+        let key = match self.load_bound.as_ref() {
+            None => ready.next(),
+            Some(bound) => {
+                let capacity = bound.capacity(services.len());
+                // If every ready endpoint is at its bound, which can only happen when endpoints
+                // that aren't ready hold less than their share, use the first one anyway.
+                let mut first = None;
+                ready
+                    .find(|key| {
+                        first.get_or_insert(*key);
+                        bound.in_flight(key) < capacity
+                    })
+                    .or(first)
+            }
+        }
+        .expect("called before ready")
+        .clone();
+        trace!(hash, "hashed request to ready endpoint");
+
+        let in_flight = self.load_bound.as_mut().map(|bound| bound.track(&key));
+        let fut = self.services.call_ready(&key, request);
+        ResponseFuture::new(fut, in_flight)
+    }
+}
+
+// ===== impl LoadBound =====
+
+impl<K> LoadBound<K>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Returns the number of in-flight requests that an endpoint may have before requests are
+    /// sent elsewhere, counting the request being sent.
+    fn capacity(&self, endpoints: usize) -> usize {
+        let total = Arc::strong_count(&self.total) - 1;
+        let average = (total + 1) as f64 / endpoints as f64;
+        (self.factor * average).ceil() as usize
+    }
+
+    fn in_flight(&self, key: &K) -> usize {
+        self.in_flight
+            .get(key)
+            .map_or(0, |count| Arc::strong_count(count) - 1)
+    }
+
+    /// Counts a request to `key` as in flight until the returned handles are dropped.
+    fn track(&mut self, key: &K) -> (Arc<()>, Arc<()>) {
+        let count = self
+            .in_flight
+            .entry(key.clone())
+            .or_insert_with(|| Arc::new(()));
+        (count.clone(), self.total.clone())
+    }
+}
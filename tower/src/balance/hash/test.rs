@@ -0,0 +1,243 @@
+use crate::discover::ServiceList;
+use futures_util::pin_mut;
+use std::task::Poll;
+use tokio_test::{assert_pending, assert_ready_ok, task};
+use tower_test::{assert_request_eq, mock};
+
+use super::*;
+
+type Req = &'static str;
+
+fn by_request(req: &Req) -> Req {
+    req
+}
+
+#[tokio::test]
+async fn empty() {
+    let empty: Vec<mock::Mock<Req, Req>> = vec![];
+    let disco = ServiceList::new(empty);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request));
+    assert_pending!(svc.poll_ready());
+}
+
+#[tokio::test]
+async fn same_key_same_endpoint() {
+    let (mock_a, handle_a) = mock::pair::<Req, Req>();
+    let (mock_b, handle_b) = mock::pair::<Req, Req>();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request));
+
+    handle_a.allow(1);
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().len(), 2);
+
+    // Find out which endpoint "key" hashes to.
+    let mut fut = task::spawn(svc.call("key"));
+    let first = if let Poll::Ready(Some((req, rsp))) = handle_a.as_mut().poll_request() {
+        assert_eq!(req, "key");
+        rsp.send_response("a");
+        "a"
+    } else {
+        assert_request_eq!(handle_b, "key").send_response("b");
+        "b"
+    };
+    assert_eq!(assert_ready_ok!(fut.poll()), first);
+
+    // Subsequent requests for the same key go to the same endpoint.
+    for _ in 0..4 {
+        handle_a.allow(1);
+        handle_b.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+
+        let mut fut = task::spawn(svc.call("key"));
+        if first == "a" {
+            assert_request_eq!(handle_a, "key").send_response("a");
+        } else {
+            assert_request_eq!(handle_b, "key").send_response("b");
+        }
+        assert_eq!(assert_ready_ok!(fut.poll()), first);
+    }
+}
+
+#[tokio::test]
+async fn falls_back_when_not_ready() {
+    let (mock_a, handle_a) = mock::pair::<Req, Req>();
+    let (mock_b, handle_b) = mock::pair::<Req, Req>();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request));
+
+    // Only one endpoint is ready, so every key must be sent to it.
+    handle_a.allow(0);
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+
+    for key in &["a", "b", "c", "d"] {
+        handle_b.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(key));
+        assert_request_eq!(handle_b, *key).send_response("b");
+        assert_eq!(assert_ready_ok!(fut.poll()), "b");
+    }
+    assert_pending!(handle_a.as_mut().poll_request());
+}
+
+#[tokio::test]
+async fn load_bound_spills_to_next_endpoint() {
+    let (mock_a, handle_a) = mock::pair::<Req, Req>();
+    let (mock_b, handle_b) = mock::pair::<Req, Req>();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request).with_load_bound(1.0));
+
+    handle_a.allow(2);
+    handle_b.allow(2);
+    assert_ready_ok!(svc.poll_ready());
+
+    // Leave the first request in flight.
+    let mut first = task::spawn(svc.call("key"));
+    let (first_rsp, on_a) = if let Poll::Ready(Some((req, rsp))) = handle_a.as_mut().poll_request()
+    {
+        assert_eq!(req, "key");
+        (rsp, true)
+    } else {
+        (assert_request_eq!(handle_b, "key"), false)
+    };
+
+    // With one request in flight, each of the two endpoints may have one, so the second request
+    // for the same key goes to the other endpoint.
+    assert_ready_ok!(svc.poll_ready());
+    let mut second = task::spawn(svc.call("key"));
+    if on_a {
+        assert_request_eq!(handle_b, "key").send_response("b");
+    } else {
+        assert_request_eq!(handle_a, "key").send_response("a");
+    }
+    assert_ready_ok!(second.poll());
+
+    // Once the first request completes, the key goes back to its endpoint.
+    first_rsp.send_response("first");
+    assert_eq!(assert_ready_ok!(first.poll()), "first");
+    assert_ready_ok!(svc.poll_ready());
+    let mut third = task::spawn(svc.call("key"));
+    if on_a {
+        assert_request_eq!(handle_a, "key").send_response("a");
+    } else {
+        assert_request_eq!(handle_b, "key").send_response("b");
+    }
+    assert_ready_ok!(third.poll());
+}
+
+#[tokio::test]
+async fn drops_failed_endpoint() {
+    let (mut svc, mut handle) = mock::spawn_with(|s: mock::Mock<Req, Req>| {
+        let disco = ServiceList::new(vec![s]);
+        Balance::new(disco, by_request)
+    });
+
+    handle.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call("key"));
+    assert_request_eq!(handle, "key").send_response("ok");
+    assert_eq!(assert_ready_ok!(fut.poll()), "ok");
+
+    handle.send_error("endpoint lost");
+    assert_pending!(svc.poll_ready());
+    assert!(
+        svc.get_ref().is_empty(),
+        "balancer must drop failed endpoints"
+    );
+}
+
+#[tokio::test]
+async fn rechecks_ready_endpoints() {
+    let (mock_a, handle_a) = mock::pair::<Req, Req>();
+    let (mock_b, handle_b) = mock::pair::<Req, Req>();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request));
+
+    handle_a.allow(1);
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().len(), 2);
+
+    // An endpoint that fails while it's ready is dropped before a request can be sent to it.
+    handle_a.send_error("endpoint lost");
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().len(), 1);
+
+    for key in &["a", "b", "c", "d"] {
+        handle_b.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(key));
+        assert_request_eq!(handle_b, *key).send_response("b");
+        assert_eq!(assert_ready_ok!(fut.poll()), "b");
+    }
+}
+
+#[tokio::test]
+async fn load_bound_counts_requests_to_reinserted_endpoint() {
+    use crate::discover::Change;
+    use futures_util::{stream, StreamExt};
+    use std::convert::Infallible;
+    use tokio::sync::mpsc;
+
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let disco = stream::poll_fn(move |cx| rx.poll_recv(cx)).map(Ok::<_, Infallible>);
+    let mut svc = mock::Spawn::new(Balance::new(disco, by_request).with_load_bound(1.0));
+
+    let (mock_a, handle_a) = mock::pair::<Req, Req>();
+    let (mock_b, handle_b) = mock::pair::<Req, Req>();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    handle_a.allow(1);
+    handle_b.allow(1);
+    tx.send(Change::Insert("a", mock_a)).unwrap();
+    tx.send(Change::Insert("b", mock_b)).unwrap();
+    assert_ready_ok!(svc.poll_ready());
+
+    // Leave a request in flight to the endpoint that the key hashes to.
+    let mut first = task::spawn(svc.call("key"));
+    let (first_rsp, on_a) = if let Poll::Ready(Some((req, rsp))) = handle_a.as_mut().poll_request()
+    {
+        assert_eq!(req, "key");
+        (rsp, true)
+    } else {
+        (assert_request_eq!(handle_b, "key"), false)
+    };
+
+    // Replace that endpoint while the request is in flight.
+    let (mock_c, handle_c) = mock::pair::<Req, Req>();
+    pin_mut!(handle_c);
+    handle_c.allow(1);
+    let replaced = if on_a { "a" } else { "b" };
+    tx.send(Change::Remove(replaced)).unwrap();
+    tx.send(Change::Insert(replaced, mock_c)).unwrap();
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().len(), 2);
+
+    // The request in flight still counts towards the endpoint, so the next request for the same
+    // key goes to the other endpoint.
+    let mut second = task::spawn(svc.call("key"));
+    if on_a {
+        assert_request_eq!(handle_b, "key").send_response("b");
+    } else {
+        assert_request_eq!(handle_a, "key").send_response("a");
+    }
+    assert_ready_ok!(second.poll());
+    assert_pending!(handle_c.as_mut().poll_request());
+
+    first_rsp.send_response("first");
+    assert_eq!(assert_ready_ok!(first.poll()), "first");
+}
@@ -0,0 +1,24 @@
+//! This module implements a least-loaded load balancer.
+//!
+//! Whenever a request comes in, the balancer compares the [`Load`](crate::load::Load) of _every_
+//! ready service and issues the request to whichever service is least loaded. This finds the true
+//! minimum, where [`p2c`](crate::balance::p2c) only samples two services, at the cost of a full
+//! scan over the ready services for each request. It is therefore best suited to small sets of
+//! services, where sampling two of them at random gives a noisy picture of the overall load.
+//!
+//! The balance service and layer implementations rely on _service discovery_ to provide the
+//! underlying set of services to balance requests across. This happens through the
+//! [`Discover`](crate::discover::Discover) trait, which is essentially a [`Stream`] that indicates
+//! when services become available or go away. If you have a fixed set of services, consider using
+//! [`ServiceList`](crate::discover::ServiceList).
+//!
+//! [`Stream`]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+
+mod make;
+mod service;
+
+#[cfg(test)]
+mod test;
+
+pub use make::{LeastLoaded, MakeBalance, MakeBalanceLayer, MakeFuture};
+pub use service::Balance;
@@ -0,0 +1,50 @@
+use crate::discover::ServiceList;
+use crate::load;
+use futures_util::pin_mut;
+use tokio_test::{assert_pending, assert_ready_ok, task};
+use tower_test::{assert_request_eq, mock};
+
+use super::*;
+
+#[tokio::test]
+async fn empty() {
+    let empty: Vec<load::Constant<mock::Mock<(), &'static str>, usize>> = vec![];
+    let disco = ServiceList::new(empty);
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+    assert_pending!(svc.poll_ready());
+}
+
+#[tokio::test]
+async fn picks_least_loaded_endpoint() {
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    let (mock_c, handle_c) = mock::pair();
+    let mock_a = load::Constant::new(mock_a, 3);
+    let mock_b = load::Constant::new(mock_b, 1);
+    let mock_c = load::Constant::new(mock_c, 2);
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    pin_mut!(handle_c);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b, mock_c]);
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    handle_a.allow(1);
+    handle_b.allow(1);
+    handle_c.allow(1);
+    for _ in 0..3 {
+        handle_b.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        assert_request_eq!(handle_b, ()).send_response("b");
+        assert_eq!(assert_ready_ok!(fut.poll()), "b");
+    }
+
+    // When the least loaded endpoint is not ready, the next least loaded one
+    // is used.
+    handle_b.allow(0);
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_c, ()).send_response("c");
+    assert_eq!(assert_ready_ok!(fut.poll()), "c");
+}
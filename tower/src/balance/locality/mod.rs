@@ -0,0 +1,53 @@
+//! This module implements a zone-aware load balancer.
+//!
+//! When endpoints are spread across zones (or racks, regions, data centers, ...), sending requests
+//! to an endpoint in another zone usually costs more, in latency or in money, than sending them to
+//! a local one. The balancer in this module groups endpoints by a zone label derived from each
+//! endpoint's key, and prefers the zones it is given in priority order, typically starting with
+//! the local zone.
+//!
+//! Within a zone, requests are spread using [`p2c`](crate::balance::p2c). A zone is used as long
+//! as the fraction of its endpoints that are ready is at least the balancer's _spill-over
+//! threshold_. When the local zone drops below the threshold, requests spill over to the next zone
+//! in priority order that is above it. If no zone is above the threshold, requests go to the
+//! highest-priority zone that has any ready endpoint at all.
+//!
+//! Zones that are discovered but were not listed when the balancer was constructed are used after
+//! all listed zones, in the order they were discovered in.
+//!
+//! The balance service relies on _service discovery_ to provide the underlying set of services to
+//! balance requests across. This happens through the [`Discover`](crate::discover::Discover)
+//! trait, which is essentially a [`Stream`] that indicates when services become available or go
+//! away.
+//!
+//! # Examples
+//!
+//! ```rust
+//! use tower::balance::locality::Balance;
+//! use tower::discover::Discover;
+//! # use tower::{load::Load, Service};
+//!
+//! // Endpoints are keyed by their zone and address.
+//! fn zone_aware<D, Req>(discover: D) -> impl Service<Req>
+//! where
+//!     D: Discover<Key = (&'static str, std::net::SocketAddr)> + Unpin,
+//!     D::Error: Into<tower::BoxError>,
+//!     D::Service: Service<Req> + Load,
+//!     <D::Service as Service<Req>>::Error: Into<tower::BoxError>,
+//!     <D::Service as Load>::Metric: std::fmt::Debug,
+//! {
+//!     // Prefer the local zone, then a nearby one. Spill over once fewer than 70% of the
+//!     // endpoints in a zone are ready.
+//!     Balance::new(discover, |key: &(&'static str, _)| key.0, vec!["us-east-1a", "us-east-1b"])
+//!         .with_threshold(0.7)
+//! }
+//! ```
+//!
+//! [`Stream`]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+
+mod service;
+
+#[cfg(test)]
+mod test;
+
+pub use service::{Balance, Locate};
@@ -0,0 +1,358 @@
+use super::super::{error, p2c};
+use crate::discover::{Change, Discover, Metadata, MetadataReceiver};
+use crate::load::Load;
+use futures_core::{ready, Stream};
+use futures_util::future;
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// The spill-over threshold used by [`Balance::new`].
+const DEFAULT_THRESHOLD: f64 = 0.5;
+
+/// Determines the zone that an endpoint belongs to.
+///
+/// See the [module-level documentation](..) for details.
+pub trait Locate<K> {
+    /// The type of zone labels.
+    type Zone: Hash + Eq;
+
+    /// Returns the zone that the endpoint identified by `key` belongs to.
+    fn locate(&mut self, key: &K) -> Self::Zone;
+}
+
+impl<F, K, Z> Locate<K> for F
+where
+    F: FnMut(&K) -> Z,
+    Z: Hash + Eq,
+{
+    type Zone = Z;
+
+    fn locate(&mut self, key: &K) -> Z {
+        self(key)
+    }
+}
+
+/// Distributes requests across services, preferring zones in priority order.
+///
+/// See the [module-level documentation](..) for details.
+///
+/// Note that [`Balance`] requires that the [`Discover`] you use is [`Unpin`] in order to implement
+/// [`Service`]. This is because it needs to be accessed from [`Service::poll_ready`], which takes
+/// `&mut self`. You can achieve this easily by wrapping your [`Discover`] in [`Box::pin`] before you
+/// construct the [`Balance`] instance. For more details, see [#319].
+///
+/// [`Box::pin`]: std::boxed::Box::pin()
+/// [#319]: https://github.com/tower-rs/tower/issues/319
+pub struct Balance<D, L, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+    L: Locate<D::Key>,
+{
+    discover: D,
+    locate: L,
+
+    /// Zones in priority order.
+    zones: Vec<Zone<L::Zone, D::Key, D::Service, Req>>,
+    /// The priority of each zone that was listed at construction.
+    priorities: HashMap<L::Zone, usize>,
+    /// The zone that each endpoint was placed in.
+    endpoints: HashMap<D::Key, L::Zone>,
+    metadata_rx: Option<MetadataReceiver<D::Key>>,
+    threshold: f64,
+
+    ready_zone: Option<usize>,
+}
+
+struct Zone<Z, K, S, Req>
+where
+    K: Hash + Eq,
+{
+    label: Z,
+    priority: usize,
+    balance: p2c::Balance<Changes<K, S>, Req>,
+}
+
+/// A [`Discover`] that yields the changes fed to it by the zone-aware balancer.
+struct Changes<K, S> {
+    changes: VecDeque<Change<K, S>>,
+}
+
+impl<D, L, Req> fmt::Debug for Balance<D, L, Req>
+where
+    D: Discover + fmt::Debug,
+    D::Key: Hash + fmt::Debug,
+    D::Service: fmt::Debug,
+    L: Locate<D::Key>,
+    L::Zone: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("discover", &self.discover)
+            .field(
+                "zones",
+                &self.zones.iter().map(|z| &z.label).collect::<Vec<_>>(),
+            )
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+}
+
+impl<D, L, Req> Balance<D, L, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    L: Locate<D::Key>,
+{
+    /// Constructs a zone-aware load balancer that places endpoints in zones using `locate`, and
+    /// prefers zones in the order they are listed in `zones`.
+    ///
+    /// The spill-over threshold defaults to one half.
+    pub fn new<I>(discover: D, locate: L, zones: I) -> Self
+    where
+        I: IntoIterator<Item = L::Zone>,
+    {
+        let mut priorities = HashMap::new();
+        for zone in zones {
+            let priority = priorities.len();
+            priorities.entry(zone).or_insert(priority);
+        }
+        Self {
+            discover,
+            locate,
+            zones: Vec::new(),
+            priorities,
+            endpoints: HashMap::new(),
+            metadata_rx: None,
+            threshold: DEFAULT_THRESHOLD,
+            ready_zone: None,
+        }
+    }
+
+    /// Sets the spill-over threshold: the fraction of a zone's endpoints that must be ready for
+    /// requests to be sent to it rather than to a lower-priority zone.
+    ///
+    /// A threshold of zero only spills over when no endpoint in a zone is ready.
+    ///
+    /// # Panics
+    ///
+    /// If `threshold` is not between zero and one, inclusive.
+    pub fn with_threshold(mut self, threshold: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&threshold),
+            "threshold must be between 0 and 1"
+        );
+        self.threshold = threshold;
+        self
+    }
+
+    /// Receives the [`Metadata`] of endpoints from `metadata`, alongside the [`Discover`], and
+    /// passes it on to the balancer for each endpoint's zone.
+    ///
+    /// See [`p2c::Balance::with_metadata`] for details.
+    pub fn with_metadata(mut self, metadata: MetadataReceiver<D::Key>) -> Self {
+        self.metadata_rx = Some(metadata);
+        self
+    }
+
+    /// Returns the number of endpoints currently tracked by the balancer.
+    pub fn len(&self) -> usize {
+        self.zones.iter().map(|z| z.balance.len()).sum()
+    }
+
+    /// Returns whether or not the balancer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.zones.iter().all(|z| z.balance.is_empty())
+    }
+}
+
+impl<D, L, Req> Balance<D, L, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req> + Load,
+    <D::Service as Load>::Metric: std::fmt::Debug,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    L: Locate<D::Key>,
+    L::Zone: Clone,
+{
+    /// Polls `discover` for updates, passing each change on to the balancer for its zone.
+    fn update_zones_from_discover(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(), error::Discover>>> {
+        debug!("updating from discover");
+        loop {
+            match ready!(Pin::new(&mut self.discover).poll_discover(cx))
+                .transpose()
+                .map_err(|e| error::Discover(e.into()))?
+            {
+                None => return Poll::Ready(None),
+                Some(Change::Remove(key)) => {
+                    trace!("remove");
+                    if let Some(zone) = self.endpoints.remove(&key) {
+                        let index = self.zone_index(&zone);
+                        self.feed(index, Change::Remove(key));
+                    }
+                }
+                Some(Change::Insert(key, svc)) => {
+                    trace!("insert");
+                    let zone = self.locate.locate(&key);
+                    if let Some(prior) = self.endpoints.insert(key.clone(), zone.clone()) {
+                        if prior != zone {
+                            // The endpoint moved between zones.
+                            let index = self.zone_index(&prior);
+                            self.feed(index, Change::Remove(key.clone()));
+                        }
+                    }
+                    let index = self.zone_index(&zone);
+                    self.feed(index, Change::Insert(key, svc));
+                }
+            }
+        }
+    }
+
+    /// Passes the metadata that was sent since the balancer was last polled on to the balancer
+    /// for each endpoint's zone.
+    fn update_metadata(&mut self) {
+        while let Some((key, metadata)) = self.metadata_rx.as_mut().and_then(|rx| rx.try_recv()) {
+            self.set_metadata(key, metadata);
+        }
+    }
+
+    fn set_metadata(&mut self, key: D::Key, metadata: Option<Metadata>) {
+        let zone = match self.endpoints.get(&key) {
+            Some(zone) => zone.clone(),
+            None => self.locate.locate(&key),
+        };
+        let index = self.zone_index(&zone);
+        self.zones[index].balance.set_metadata(key, metadata);
+    }
+
+    /// Returns the index of `zone` in `zones`, adding it if it is new.
+    fn zone_index(&mut self, zone: &L::Zone) -> usize {
+        if let Some(index) = self.zones.iter().position(|z| z.label == *zone) {
+            return index;
+        }
+
+        // Unlisted zones come after all listed zones, in the order they were discovered.
+        let priority = match self.priorities.get(zone) {
+            Some(priority) => *priority,
+            None => self.priorities.len() + self.zones.len(),
+        };
+        let index = self
+            .zones
+            .iter()
+            .position(|z| z.priority > priority)
+            .unwrap_or(self.zones.len());
+        trace!(index, priority, "adding zone");
+        self.zones.insert(
+            index,
+            Zone {
+                label: zone.clone(),
+                priority,
+                balance: p2c::Balance::new(Changes {
+                    changes: VecDeque::new(),
+                }),
+            },
+        );
+        // Adding a zone shifts the indices of lower-priority zones.
+        if let Some(ready) = self.ready_zone.as_mut() {
+            if *ready >= index {
+                *ready += 1;
+            }
+        }
+        index
+    }
+
+    fn feed(&mut self, index: usize, change: Change<D::Key, D::Service>) {
+        self.zones[index]
+            .balance
+            .discover_mut()
+            .changes
+            .push_back(change);
+    }
+
+    /// Selects the zone that the next request should be sent to, among the zones that are ready.
+    fn select_zone(&self, ready: &[bool]) -> Option<usize> {
+        let healthy = self.zones.iter().zip(ready).position(|(zone, ready)| {
+            let len = zone.balance.len();
+            *ready && zone.balance.ready_len() as f64 >= self.threshold * len as f64
+        });
+        healthy.or_else(|| ready.iter().position(|ready| *ready))
+    }
+}
+
+impl<D, L, Req> Service<Req> for Balance<D, L, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req> + Load,
+    <D::Service as Load>::Metric: std::fmt::Debug,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+    L: Locate<D::Key>,
+    L::Zone: Clone,
+{
+    type Response = <D::Service as Service<Req>>::Response;
+    type Error = crate::BoxError;
+    type Future = future::MapErr<
+        <D::Service as Service<Req>>::Future,
+        fn(<D::Service as Service<Req>>::Error) -> crate::BoxError,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let _ = self.update_zones_from_discover(cx)?;
+        self.update_metadata();
+
+        // Drive every zone, so that endpoints that become ready are noticed and the health of
+        // each zone is up to date.
+        let mut ready = Vec::with_capacity(self.zones.len());
+        for zone in &mut self.zones {
+            ready.push(zone.balance.poll_ready(cx)?.is_ready());
+        }
+
+        self.ready_zone = self.select_zone(&ready);
+        match self.ready_zone {
+            Some(index) => {
+                trace!(zone = index, "selected zone");
+                Poll::Ready(Ok(()))
+            }
+            // Every zone has registered interest in updates from its pending services, and we
+            // have registered interest in updates from discover.
+            None => Poll::Pending,
+        }
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        let index = self.ready_zone.take().expect("called before ready");
+        self.zones[index].balance.call(request)
+    }
+}
+
+// Changes are never pinned in place.
+impl<K, S> Unpin for Changes<K, S> {}
+
+impl<K, S> Stream for Changes<K, S> {
+    type Item = Result<Change<K, S>, Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        // The zone-aware balancer feeds changes in before driving each zone's balancer, and is
+        // itself woken by its own `Discover`, so there is no need to register for wakeups here.
+        match self.changes.pop_front() {
+            Some(change) => Poll::Ready(Some(Ok(change))),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use crate::discover::ServiceList;
+use crate::load;
+use futures_util::pin_mut;
+use tokio_test::{assert_pending, assert_ready_ok, task};
+use tower_test::{assert_request_eq, mock};
+
+use super::*;
+
+type Mock = load::Constant<mock::Mock<(), &'static str>, usize>;
+
+/// The first two endpoints are local, the rest are remote.
+fn zone(index: &usize) -> &'static str {
+    if *index < 2 {
+        "local"
+    } else {
+        "remote"
+    }
+}
+
+fn pair() -> (Mock, mock::Handle<(), &'static str>) {
+    let (svc, handle) = mock::pair();
+    (load::Constant::new(svc, 0), handle)
+}
+
+#[tokio::test]
+async fn empty() {
+    let empty: Vec<Mock> = vec![];
+    let disco = ServiceList::new(empty);
+    let mut svc = mock::Spawn::new(Balance::new(disco, zone, vec!["local", "remote"]));
+    assert_pending!(svc.poll_ready());
+}
+
+#[tokio::test]
+async fn prefers_local_zone() {
+    let (mock_a, handle_a) = pair();
+    let (mock_b, handle_b) = pair();
+    let (mock_c, handle_c) = pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    pin_mut!(handle_c);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b, mock_c]);
+    let mut svc = mock::Spawn::new(Balance::new(disco, zone, vec!["local", "remote"]));
+
+    // Half of the local zone is ready, which meets the default threshold.
+    handle_a.allow(1);
+    handle_b.allow(0);
+    handle_c.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(svc.get_ref().len(), 3);
+
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response("a");
+    assert_eq!(assert_ready_ok!(fut.poll()), "a");
+    assert_pending!(handle_c.as_mut().poll_request());
+}
+
+#[tokio::test]
+async fn spills_over_below_threshold() {
+    let (mock_a, handle_a) = pair();
+    let (mock_b, handle_b) = pair();
+    let (mock_c, handle_c) = pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    pin_mut!(handle_c);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b, mock_c]);
+    let mut svc =
+        mock::Spawn::new(Balance::new(disco, zone, vec!["local", "remote"]).with_threshold(1.0));
+
+    // Only half of the local zone is ready, so requests spill over to the remote zone.
+    handle_a.allow(1);
+    handle_b.allow(0);
+    handle_c.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_c, ()).send_response("c");
+    assert_eq!(assert_ready_ok!(fut.poll()), "c");
+
+    // Once the local zone recovers, requests go back to it.
+    handle_a.allow(1);
+    handle_b.allow(1);
+    handle_c.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    let mut fut = task::spawn(svc.call(()));
+    assert_pending!(handle_c.as_mut().poll_request());
+    if let std::task::Poll::Ready(Some(((), rsp))) = handle_a.as_mut().poll_request() {
+        rsp.send_response("a");
+    } else {
+        assert_request_eq!(handle_b, ()).send_response("b");
+    }
+    assert_ne!(assert_ready_ok!(fut.poll()), "c");
+}
+
+#[tokio::test]
+async fn falls_back_to_any_ready_zone() {
+    let (mock_a, handle_a) = pair();
+    let (mock_b, handle_b) = pair();
+    let (mock_c, handle_c) = pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    pin_mut!(handle_c);
+
+    // List the remote zone first, so that it is preferred.
+    let disco = ServiceList::new(vec![mock_a, mock_b, mock_c]);
+    let mut svc =
+        mock::Spawn::new(Balance::new(disco, zone, vec!["remote", "local"]).with_threshold(1.0));
+
+    // No zone meets the threshold, so requests go to the highest-priority zone that has a ready
+    // endpoint.
+    handle_a.allow(1);
+    handle_b.allow(0);
+    handle_c.allow(0);
+    assert_ready_ok!(svc.poll_ready());
+
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response("a");
+    assert_eq!(assert_ready_ok!(fut.poll()), "a");
+}
+
+#[tokio::test]
+async fn passes_metadata_to_zones() {
+    use crate::discover::{metadata_channel, Metadata};
+
+    let (mock_a, handle_a) = pair();
+    let (mock_b, handle_b) = pair();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let (metadata_tx, metadata_rx) = metadata_channel();
+    metadata_tx.send(0, Metadata::new().with_draining(true));
+    let balance = Balance::new(disco, zone, vec!["local"]).with_metadata(metadata_rx);
+    let mut svc = mock::Spawn::new(balance);
+
+    // Both endpoints are local and equally loaded, but one of them is draining.
+    for _ in 0..4 {
+        handle_a.allow(1);
+        handle_b.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        assert_request_eq!(handle_b, ()).send_response("b");
+        assert_eq!(assert_ready_ok!(fut.poll()), "b");
+    }
+    assert_pending!(handle_a.as_mut().poll_request());
+}
@@ -0,0 +1,200 @@
+//! Constructing load balancers over dynamic service sets.
+//!
+//! The [`round_robin`] and [`least_loaded`] modules provide [`MakeBalance`] and
+//! [`MakeBalanceLayer`] aliases that build their own balancer, such as
+//! [`round_robin::MakeBalance`]. The balancer that is built is chosen by a [`NewBalance`]
+//! implementation.
+//!
+//! [`round_robin`]: crate::balance::round_robin
+//! [`least_loaded`]: crate::balance::least_loaded
+//! [`round_robin::MakeBalance`]: crate::balance::round_robin::MakeBalance
+
+use futures_core::ready;
+use pin_project_lite::pin_project;
+use std::marker::PhantomData;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Builds a load balancer over a service set.
+///
+/// This is implemented by a marker type in each balancer module, such as
+/// [`round_robin::RoundRobin`](crate::balance::round_robin::RoundRobin), to select the balancer
+/// that [`MakeBalance`] builds.
+pub trait NewBalance<D> {
+    /// The type of load balancer that is built.
+    type Balance;
+
+    /// Builds a load balancer over the services discovered by `discover`.
+    fn new_balance(discover: D) -> Self::Balance;
+}
+
+/// Constructs load balancers over dynamic service sets produced by a wrapped "inner" service.
+///
+/// This is effectively an implementation of [`MakeService`] except that it forwards the service
+/// descriptors (`Target`) to an inner service (`S`), and expects that service to produce a
+/// service set in the form of a [`Discover`]. It then wraps the service set in a balancer, which
+/// is built by `B`, before returning it as the "made" service.
+///
+/// See the [module-level documentation](crate::balance) for details on load balancing.
+///
+/// [`MakeService`]: crate::MakeService
+/// [`Discover`]: crate::discover::Discover
+pub struct MakeBalance<S, B> {
+    inner: S,
+    _marker: PhantomData<fn(B)>,
+}
+
+pin_project! {
+    /// A balancer in the making.
+    pub struct MakeFuture<F, B> {
+        #[pin]
+        inner: F,
+        _marker: PhantomData<fn(B)>,
+    }
+}
+
+/// Construct load balancers over dynamic service sets ([`Discover`]) produced by the "inner"
+/// service in response to requests coming from the "outer" service.
+///
+/// This construction may seem a little odd at first glance. This is not a layer that takes
+/// requests and produces responses in the traditional sense. Instead, it is more like
+/// [`MakeService`] in that it takes service _descriptors_ (see `Target` on [`MakeService`])
+/// and produces _services_. Since a balancer spreads requests across a _set_ of services,
+/// the inner service should produce a [`Discover`], not just a single
+/// [`Service`], given a service descriptor.
+///
+/// See the [module-level documentation](crate::balance) for details on load balancing.
+///
+/// [`Discover`]: crate::discover::Discover
+/// [`MakeService`]: crate::MakeService
+/// [`Service`]: crate::Service
+pub struct MakeBalanceLayer<D, B> {
+    _marker: PhantomData<fn(D, B)>,
+}
+
+// ===== impl MakeBalance =====
+
+impl<S, B> MakeBalance<S, B> {
+    /// Build balancers.
+    pub const fn new(make_discover: S) -> Self {
+        Self {
+            inner: make_discover,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, B> Clone for MakeBalance<S, B>
+where
+    S: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, Target, B> Service<Target> for MakeBalance<S, B>
+where
+    S: Service<Target>,
+    B: NewBalance<S::Response>,
+{
+    type Response = B::Balance;
+    type Error = S::Error;
+    type Future = MakeFuture<S::Future, B>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, target: Target) -> Self::Future {
+        MakeFuture {
+            inner: self.inner.call(target),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, B> fmt::Debug for MakeBalance<S, B>
+where
+    S: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Self { inner, _marker } = self;
+        f.debug_struct("MakeBalance").field("inner", inner).finish()
+    }
+}
+
+// ===== impl MakeFuture =====
+
+impl<F, T, E, B> Future for MakeFuture<F, B>
+where
+    F: Future<Output = Result<T, E>>,
+    B: NewBalance<T>,
+{
+    type Output = Result<B::Balance, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let inner = ready!(this.inner.poll(cx))?;
+        let svc = B::new_balance(inner);
+        Poll::Ready(Ok(svc))
+    }
+}
+
+impl<F, B> fmt::Debug for MakeFuture<F, B>
+where
+    F: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Self { inner, _marker } = self;
+        f.debug_struct("MakeFuture").field("inner", inner).finish()
+    }
+}
+
+// ===== impl MakeBalanceLayer =====
+
+impl<D, B> MakeBalanceLayer<D, B> {
+    /// Build balancers.
+    pub const fn new() -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<D, B> Default for MakeBalanceLayer<D, B> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<D, B> Clone for MakeBalanceLayer<D, B> {
+    fn clone(&self) -> Self {
+        Self {
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<S, B> Layer<S> for MakeBalanceLayer<S, B> {
+    type Service = MakeBalance<S, B>;
+
+    fn layer(&self, make_discover: S) -> Self::Service {
+        MakeBalance::new(make_discover)
+    }
+}
+
+impl<D, B> fmt::Debug for MakeBalanceLayer<D, B> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MakeBalanceLayer").finish()
+    }
+}
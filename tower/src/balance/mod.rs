@@ -11,6 +11,25 @@
 //! if the set of available services is not within your control, and you simply
 //! want to spread load among that set of services.
 //!
+//! For small sets of services, where sampling two of them at random gives a noisy picture, the
+//! [`round_robin`] and [`least_loaded`] middleware instead rotate through the services in a fixed
+//! order or compare the load of every ready service, respectively.
+//!
+//! If requests should instead be routed by some property of the request itself, for example to
+//! improve cache locality on the endpoints, the [`hash`] middleware implements consistent hashing
+//! over the set of available services.
+//!
+//! When follow-up requests must reach the service that served an earlier request, the [`affinity`]
+//! middleware routes requests that carry the key of a ready service to it, and otherwise falls
+//! back to [`p2c`].
+//!
+//! When services are spread across zones, the [`locality`] middleware prefers the local zone and
+//! spills over to other zones in priority order when too few local services are ready, using
+//! [`p2c`] within each zone.
+//!
+//! The [`outlier`] module can be combined with any of these balancers to temporarily stop sending
+//! requests to services whose requests keep failing.
+//!
 //! [Power of Two Random Choices]: http://www.eecs.harvard.edu/~michaelm/postscripts/handbook2001.pdf
 //!
 //! # Examples
@@ -46,5 +65,12 @@
 //! # }
 //! ```
 
+pub mod affinity;
 pub mod error;
+pub mod hash;
+pub mod least_loaded;
+pub mod locality;
+pub mod make;
+pub mod outlier;
 pub mod p2c;
+pub mod round_robin;
@@ -0,0 +1,604 @@
+//! Outlier detection for load balanced endpoints.
+//!
+//! A balancer only stops sending requests to an endpoint when the endpoint's
+//! [`Service::poll_ready`] fails. An endpoint that remains ready but fails every request it
+//! receives stays in rotation forever. Outlier detection tracks the results of the requests sent
+//! to each endpoint and temporarily _ejects_ endpoints that fail too often, in the style of
+//! [Envoy's outlier detection][envoy].
+//!
+//! [`OutlierDetectionDiscover`] wraps each discovered service in an [`OutlierDetection`]
+//! middleware. While an endpoint is ejected, its [`Service::poll_ready`] returns
+//! [`Poll::Pending`], so balancers such as [`p2c::Balance`] move it out of their ready set until
+//! the ejection expires. A [`Policy`] determines when an endpoint is considered an outlier:
+//!
+//! - [`Policy::consecutive_failures`] ejects an endpoint after a number of consecutive failed
+//!   requests.
+//! - [`Policy::success_rate`] ejects an endpoint whose success rate over a number of requests falls
+//!   below a minimum.
+//!
+//! Each time the same endpoint is ejected, the ejection lasts twice as long as the previous one, up
+//! to a maximum. The share of endpoints that may be ejected at the same time is capped, so an
+//! outage that makes every endpoint fail never ejects all of them.
+//!
+//! A request is considered to have failed if its response future resolves to an error. Responses
+//! that indicate failure in some other way (such as an HTTP 5xx status) can be turned into errors
+//! before this middleware sees them, for example with [`ServiceExt::map_result`].
+//!
+//! [envoy]: https://www.envoyproxy.io/docs/envoy/latest/intro/arch_overview/upstream/outlier
+//! [`Service::poll_ready`]: crate::Service::poll_ready
+//! [`p2c::Balance`]: crate::balance::p2c::Balance
+//! [`ServiceExt::map_result`]: crate::ServiceExt::map_result
+//!
+//! # Examples
+//!
+//! ```rust
+//! use tower::balance::outlier::{OutlierDetectionDiscover, Policy};
+//! use tower::balance::p2c::Balance;
+//! use tower::discover::ServiceList;
+//! use tower::load::Constant;
+//! use std::time::Duration;
+//! # use tower::Service;
+//!
+//! # fn wrap<S: Service<()>>(svc1: S, svc2: S) where S::Error: Into<tower::BoxError> {
+//! let policy = Policy::consecutive_failures(5)
+//!     .base_ejection_time(Duration::from_secs(10))
+//!     .max_ejection_percent(50);
+//! let discover = OutlierDetectionDiscover::new(
+//!     ServiceList::new(vec![Constant::new(svc1, 0), Constant::new(svc2, 0)]),
+//!     policy,
+//! );
+//! let balance = Balance::new(discover);
+//! # let _: Balance<_, ()> = balance;
+//! # }
+//! ```
+
+use crate::discover::{Change, Discover};
+use crate::load::Load;
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::time::{sleep_until, Instant, Sleep};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// Determines when an endpoint is ejected, and for how long.
+///
+/// See the [module-level documentation](crate::balance::outlier) for details.
+#[derive(Clone, Debug)]
+pub struct Policy {
+    detection: Detection,
+    base_ejection_time: Duration,
+    max_ejection_time: Duration,
+    max_ejection_percent: u8,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Detection {
+    ConsecutiveFailures(u32),
+    SuccessRate { minimum: f64, request_volume: u32 },
+}
+
+/// Wraps a service so that it is ejected when its requests fail too often.
+///
+/// Instances are created by [`OutlierDetectionDiscover`]; see the
+/// [module-level documentation](crate::balance::outlier) for details.
+pub struct OutlierDetection<S> {
+    inner: S,
+    endpoint: Arc<Endpoint>,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+pin_project! {
+    /// Wraps a `D`-typed stream of discovered services with [`OutlierDetection`].
+    ///
+    /// All services discovered by the same [`OutlierDetectionDiscover`] count toward the same
+    /// maximum ejection percentage.
+    #[derive(Debug)]
+    pub struct OutlierDetectionDiscover<D> {
+        #[pin]
+        discover: D,
+        group: Arc<Group>,
+    }
+}
+
+pin_project! {
+    /// Response future for [`OutlierDetection`].
+    #[derive(Debug)]
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: F,
+        endpoint: Option<Arc<Endpoint>>,
+    }
+}
+
+/// State shared by all endpoints of the same [`OutlierDetectionDiscover`].
+#[derive(Debug)]
+struct Group {
+    policy: Policy,
+    counts: Mutex<Counts>,
+}
+
+#[derive(Debug, Default)]
+struct Counts {
+    endpoints: usize,
+    ejected: usize,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    group: Arc<Group>,
+    state: Mutex<EndpointState>,
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    consecutive_failures: u32,
+    successes: u32,
+    requests: u32,
+    /// Set while the endpoint is ejected.
+    ejected_until: Option<Instant>,
+    /// When the most recent ejection ended, if the endpoint was ever ejected.
+    last_ejection_end: Option<Instant>,
+    /// The number of times the endpoint has been ejected in a row.
+    ejections: u32,
+    /// Set once the endpoint's service has been dropped, so that responses
+    /// that are still in flight no longer eject it.
+    removed: bool,
+}
+
+// ===== impl Policy =====
+
+impl Policy {
+    /// Ejects an endpoint once `failures` requests in a row have failed.
+    ///
+    /// # Panics
+    ///
+    /// If `failures` is zero.
+    pub fn consecutive_failures(failures: u32) -> Self {
+        assert!(failures > 0, "failures must be positive");
+        Self::new(Detection::ConsecutiveFailures(failures))
+    }
+
+    /// Ejects an endpoint if fewer than `minimum` (between 0.0 and 1.0) of every `request_volume`
+    /// requests succeed.
+    ///
+    /// # Panics
+    ///
+    /// If `minimum` is not between 0.0 and 1.0, or if `request_volume` is zero.
+    pub fn success_rate(minimum: f64, request_volume: u32) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&minimum),
+            "minimum success rate must be between 0.0 and 1.0"
+        );
+        assert!(request_volume > 0, "request volume must be positive");
+        Self::new(Detection::SuccessRate {
+            minimum,
+            request_volume,
+        })
+    }
+
+    fn new(detection: Detection) -> Self {
+        Self {
+            detection,
+            base_ejection_time: Duration::from_secs(30),
+            max_ejection_time: Duration::from_secs(300),
+            max_ejection_percent: 10,
+        }
+    }
+
+    /// Sets how long an endpoint is ejected for the first time. Defaults to 30 seconds.
+    ///
+    /// Every further ejection of the same endpoint lasts twice as long as the previous one, up to
+    /// the [maximum ejection time](Policy::max_ejection_time).
+    pub fn base_ejection_time(mut self, time: Duration) -> Self {
+        self.base_ejection_time = time;
+        self
+    }
+
+    /// Sets the longest time that an endpoint is ejected for. Defaults to 300 seconds.
+    ///
+    /// An endpoint that is not ejected again for this long after an ejection ends starts over at
+    /// the [base ejection time](Policy::base_ejection_time).
+    pub fn max_ejection_time(mut self, time: Duration) -> Self {
+        self.max_ejection_time = time;
+        self
+    }
+
+    /// Sets the maximum percentage of endpoints that may be ejected at the same time. Defaults to
+    /// 10.
+    ///
+    /// Unless `percent` is zero, at least one endpoint may be ejected, however few endpoints there
+    /// are. Regardless of this value, the last endpoint is never ejected.
+    ///
+    /// # Panics
+    ///
+    /// If `percent` is greater than 100.
+    pub fn max_ejection_percent(mut self, percent: u8) -> Self {
+        assert!(percent <= 100, "percent must not be greater than 100");
+        self.max_ejection_percent = percent;
+        self
+    }
+
+    fn ejection_time(&self, ejections: u32) -> Duration {
+        let factor = 2_u32.saturating_pow(ejections.saturating_sub(1));
+        self.base_ejection_time
+            .checked_mul(factor)
+            .unwrap_or(self.max_ejection_time)
+            .min(self.max_ejection_time)
+    }
+}
+
+// ===== impl OutlierDetection =====
+
+impl<S> OutlierDetection<S> {
+    fn new(inner: S, group: Arc<Group>) -> Self {
+        group.counts.lock().expect("outlier counts").endpoints += 1;
+        Self {
+            inner,
+            endpoint: Arc::new(Endpoint {
+                group,
+                state: Mutex::new(EndpointState::default()),
+            }),
+            sleep: None,
+        }
+    }
+
+    /// Returns whether the endpoint is currently ejected.
+    pub fn is_ejected(&self) -> bool {
+        self.endpoint.ejected_until().is_some()
+    }
+}
+
+impl<S, Request> Service<Request> for OutlierDetection<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(until) = self.endpoint.ejected_until() {
+            let sleep = match self.sleep {
+                Some(ref mut sleep) => {
+                    sleep.as_mut().reset(until);
+                    sleep
+                }
+                None => self.sleep.insert(Box::pin(sleep_until(until))),
+            };
+            ready!(sleep.as_mut().poll(cx));
+            self.endpoint.end_ejection();
+        }
+        self.sleep = None;
+
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        ResponseFuture {
+            inner: self.inner.call(req),
+            endpoint: Some(self.endpoint.clone()),
+        }
+    }
+}
+
+impl<S: Load> Load for OutlierDetection<S> {
+    type Metric = S::Metric;
+
+    fn load(&self) -> Self::Metric {
+        self.inner.load()
+    }
+}
+
+impl<S> Drop for OutlierDetection<S> {
+    fn drop(&mut self) {
+        let mut state = self.endpoint.state.lock().expect("outlier state");
+        state.removed = true;
+        let mut counts = self.endpoint.group.counts.lock().expect("outlier counts");
+        counts.endpoints -= 1;
+        if state.ejected_until.take().is_some() {
+            counts.ejected -= 1;
+        }
+    }
+}
+
+impl<S: fmt::Debug> fmt::Debug for OutlierDetection<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("OutlierDetection")
+            .field("inner", &self.inner)
+            .field("endpoint", &self.endpoint)
+            .finish()
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        if let Some(endpoint) = this.endpoint.take() {
+            endpoint.record(result.is_ok());
+        }
+        Poll::Ready(result)
+    }
+}
+
+// ===== impl OutlierDetectionDiscover =====
+
+impl<D> OutlierDetectionDiscover<D> {
+    /// Wraps a [`Discover`], ejecting its services according to `policy`.
+    pub fn new(discover: D, policy: Policy) -> Self
+    where
+        D: Discover,
+    {
+        Self {
+            discover,
+            group: Arc::new(Group {
+                policy,
+                counts: Mutex::new(Counts::default()),
+            }),
+        }
+    }
+}
+
+impl<D> Stream for OutlierDetectionDiscover<D>
+where
+    D: Discover,
+{
+    type Item = Result<Change<D::Key, OutlierDetection<D::Service>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Remove(k)) => Change::Remove(k),
+            Some(Change::Insert(k, svc)) => {
+                Change::Insert(k, OutlierDetection::new(svc, this.group.clone()))
+            }
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl Endpoint =====
+
+impl Endpoint {
+    fn ejected_until(&self) -> Option<Instant> {
+        self.state.lock().expect("outlier state").ejected_until
+    }
+
+    /// Records the result of a request, ejecting the endpoint if it is an outlier.
+    fn record(&self, success: bool) {
+        let mut state = self.state.lock().expect("outlier state");
+        if state.removed || state.ejected_until.is_some() {
+            // Requests that were dispatched before the endpoint was ejected
+            // don't count toward the next ejection.
+            return;
+        }
+
+        let policy = &self.group.policy;
+        let is_outlier = match policy.detection {
+            Detection::ConsecutiveFailures(failures) => {
+                if success {
+                    state.consecutive_failures = 0;
+                } else {
+                    state.consecutive_failures += 1;
+                }
+                state.consecutive_failures >= failures
+            }
+            Detection::SuccessRate {
+                minimum,
+                request_volume,
+            } => {
+                state.requests += 1;
+                if success {
+                    state.successes += 1;
+                }
+                if state.requests < request_volume {
+                    false
+                } else {
+                    let rate = f64::from(state.successes) / f64::from(state.requests);
+                    state.requests = 0;
+                    state.successes = 0;
+                    rate < minimum
+                }
+            }
+        };
+        if !is_outlier {
+            return;
+        }
+
+        let mut counts = self.group.counts.lock().expect("outlier counts");
+        let mut max_ejected = counts.endpoints * usize::from(policy.max_ejection_percent) / 100;
+        if policy.max_ejection_percent > 0 {
+            // Small groups would otherwise round down to never ejecting anything.
+            max_ejected = max_ejected.max(1);
+        }
+        if counts.ejected >= max_ejected || counts.ejected + 1 >= counts.endpoints {
+            debug!(
+                ejected = counts.ejected,
+                endpoints = counts.endpoints,
+                "not ejecting outlier; too many endpoints are ejected"
+            );
+            return;
+        }
+        counts.ejected += 1;
+
+        let now = Instant::now();
+        let recently_ejected = state
+            .last_ejection_end
+            .map(|end| now.saturating_duration_since(end) < policy.max_ejection_time)
+            .unwrap_or(false);
+        state.ejections = if recently_ejected {
+            state.ejections.saturating_add(1)
+        } else {
+            1
+        };
+        let time = policy.ejection_time(state.ejections);
+        debug!(ejections = state.ejections, ?time, "ejecting outlier");
+        state.ejected_until = Some(now + time);
+        state.consecutive_failures = 0;
+        state.requests = 0;
+        state.successes = 0;
+    }
+
+    fn end_ejection(&self) {
+        let mut state = self.state.lock().expect("outlier state");
+        if state.ejected_until.take().is_some() {
+            trace!("ejection ended");
+            state.last_ejection_end = Some(Instant::now());
+            self.group.counts.lock().expect("outlier counts").ejected -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discover::ServiceList;
+    use futures_util::StreamExt;
+    use tokio::time;
+    use tokio_test::{assert_pending, assert_ready_err, assert_ready_ok, task};
+    use tower_test::{assert_request_eq, mock};
+
+    type Mock = OutlierDetection<mock::Mock<(), ()>>;
+
+    async fn discover(n: usize, policy: Policy) -> (Vec<Mock>, Vec<mock::Handle<(), ()>>) {
+        let (services, handles): (Vec<_>, Vec<_>) = (0..n).map(|_| mock::pair()).unzip();
+        let discover = OutlierDetectionDiscover::new(ServiceList::new(services), policy);
+        let services = discover
+            .map(|change| match change.unwrap() {
+                Change::Insert(_, svc) => svc,
+                Change::Remove(_) => unreachable!(),
+            })
+            .collect()
+            .await;
+        (services, handles)
+    }
+
+    async fn fail(svc: &mut Mock, handle: &mut mock::Handle<(), ()>) {
+        handle.allow(1);
+        task::spawn(()).enter(|cx, _| assert_ready_ok!(svc.poll_ready(cx)));
+        let mut rsp = task::spawn(svc.call(()));
+        assert_request_eq!(handle, ()).send_error("failed");
+        assert_ready_err!(rsp.poll());
+    }
+
+    #[tokio::test]
+    async fn consecutive_failures() {
+        time::pause();
+
+        let policy = Policy::consecutive_failures(2)
+            .base_ejection_time(Duration::from_secs(1))
+            .max_ejection_percent(50);
+        let (mut services, mut handles) = discover(2, policy).await;
+
+        fail(&mut services[0], &mut handles[0]).await;
+        assert!(!services[0].is_ejected());
+        fail(&mut services[0], &mut handles[0]).await;
+        assert!(services[0].is_ejected());
+
+        let mut ready = task::spawn(());
+        handles[0].allow(1);
+        assert_pending!(ready.enter(|cx, _| services[0].poll_ready(cx)));
+
+        time::sleep(Duration::from_secs(1)).await;
+        assert!(ready.is_woken());
+        assert_ready_ok!(ready.enter(|cx, _| services[0].poll_ready(cx)));
+        assert!(!services[0].is_ejected());
+    }
+
+    #[tokio::test]
+    async fn ejection_time_grows() {
+        time::pause();
+
+        let policy = Policy::consecutive_failures(1)
+            .base_ejection_time(Duration::from_secs(1))
+            .max_ejection_time(Duration::from_secs(3))
+            .max_ejection_percent(50);
+        let (mut services, mut handles) = discover(2, policy).await;
+
+        for expected in &[1, 2, 3, 3] {
+            fail(&mut services[0], &mut handles[0]).await;
+            let mut ready = task::spawn(());
+            assert_pending!(ready.enter(|cx, _| services[0].poll_ready(cx)));
+
+            time::sleep(Duration::from_secs(*expected - 1)).await;
+            assert_pending!(ready.enter(|cx, _| services[0].poll_ready(cx)));
+            time::sleep(Duration::from_secs(1)).await;
+            handles[0].allow(1);
+            assert_ready_ok!(ready.enter(|cx, _| services[0].poll_ready(cx)));
+        }
+    }
+
+    #[tokio::test]
+    async fn success_rate() {
+        time::pause();
+
+        let policy = Policy::success_rate(0.5, 4).max_ejection_percent(50);
+        let (mut services, mut handles) = discover(2, policy).await;
+
+        for _ in 0..3 {
+            fail(&mut services[0], &mut handles[0]).await;
+        }
+        assert!(!services[0].is_ejected());
+
+        handles[0].allow(1);
+        task::spawn(()).enter(|cx, _| assert_ready_ok!(services[0].poll_ready(cx)));
+        let mut rsp = task::spawn(services[0].call(()));
+        assert_request_eq!(handles[0], ()).send_response(());
+        assert_ready_ok!(rsp.poll());
+        assert!(services[0].is_ejected());
+    }
+
+    #[tokio::test]
+    async fn ejects_one_of_few_endpoints() {
+        time::pause();
+
+        // The default of 10% rounds down to zero endpoints out of three.
+        let policy = Policy::consecutive_failures(1);
+        let (mut services, mut handles) = discover(3, policy).await;
+
+        fail(&mut services[0], &mut handles[0]).await;
+        assert!(services[0].is_ejected());
+        fail(&mut services[1], &mut handles[1]).await;
+        assert!(!services[1].is_ejected());
+    }
+
+    #[tokio::test]
+    async fn never_ejects_all_endpoints() {
+        time::pause();
+
+        let policy = Policy::consecutive_failures(1).max_ejection_percent(100);
+        let (mut services, mut handles) = discover(2, policy).await;
+
+        fail(&mut services[0], &mut handles[0]).await;
+        assert!(services[0].is_ejected());
+        fail(&mut services[1], &mut handles[1]).await;
+        assert!(!services[1].is_ejected());
+
+        // Once an ejected endpoint goes away, it no longer counts.
+        services.remove(0);
+        let (svc, handle) = mock::pair();
+        let group = services[0].endpoint.group.clone();
+        services.push(OutlierDetection::new(svc, group));
+        handles.remove(0);
+        handles.push(handle);
+        fail(&mut services[0], &mut handles[0]).await;
+        assert!(services[0].is_ejected());
+    }
+}
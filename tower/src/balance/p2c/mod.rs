@@ -25,12 +25,21 @@
 //! that lets you specify the random seed to use. Usually the former is what you'll want, though
 //! the latter may come in handy for reproducibility or to reduce reliance on the operating system.
 //!
+//! By default, an error from service discovery causes the balancer to fail. A [`DiscoverPolicy`]
+//! can instead keep serving requests with the last known set of services while discovery is
+//! retried.
+//!
+//! To debug how load is spread, [`Balance::snapshot`] lists every endpoint along with whether it
+//! is ready and its current load. [`Balance::with_pick_counts`] additionally counts how many times
+//! each endpoint is picked.
+//!
 //! [Power of Two Random Choices]: http://www.eecs.harvard.edu/~michaelm/postscripts/handbook2001.pdf
 //! [finagle]: https://twitter.github.io/finagle/guide/Clients.html#power-of-two-choices-p2c-least-loaded
 //! [`Stream`]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
 
 mod layer;
 mod make;
+mod policy;
 mod service;
 
 #[cfg(test)]
@@ -38,4 +47,5 @@ mod test;
 
 pub use layer::MakeBalanceLayer;
 pub use make::{MakeBalance, MakeFuture};
-pub use service::Balance;
+pub use policy::DiscoverPolicy;
+pub use service::{Balance, Snapshot};
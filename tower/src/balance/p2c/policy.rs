@@ -0,0 +1,116 @@
+use super::super::error;
+use crate::retry::backoff::{Backoff, ExponentialBackoff, ExponentialBackoffMaker, MakeBackoff};
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{Instant, Sleep};
+use tracing::{debug, warn};
+
+/// Determines how a [`Balance`](super::Balance) handles errors from its [`Discover`].
+///
+/// By default, the first discovery error is returned from the balancer's `poll_ready`, after which
+/// the balancer can no longer be used. With [`DiscoverPolicy::retry`], the balancer instead keeps
+/// serving requests with the last known set of endpoints, and polls the [`Discover`] again after a
+/// backoff. The [`Discover`] must therefore be able to produce further changes after it yields an
+/// error.
+///
+/// [`Discover`]: crate::discover::Discover
+#[derive(Clone, Debug, Default)]
+pub struct DiscoverPolicy {
+    backoff: Option<ExponentialBackoffMaker>,
+    fail_after: Option<Duration>,
+}
+
+/// Tracks a balancer's discovery errors according to its [`DiscoverPolicy`].
+#[derive(Debug)]
+pub(super) struct Recovery {
+    policy: DiscoverPolicy,
+    backoff: Option<ExponentialBackoff>,
+    delay: Option<Pin<Box<Sleep>>>,
+    failing_since: Option<Instant>,
+}
+
+// ==== impl DiscoverPolicy ====
+
+impl DiscoverPolicy {
+    /// Returns the first discovery error from the balancer.
+    ///
+    /// This is the default.
+    pub fn fail_fast() -> Self {
+        Self::default()
+    }
+
+    /// Keeps the last known set of endpoints when discovery fails, polling discovery again after
+    /// each error once `backoff` has elapsed.
+    ///
+    /// The backoff is reset by each successful update from discovery.
+    pub fn retry(backoff: ExponentialBackoffMaker) -> Self {
+        Self {
+            backoff: Some(backoff),
+            fail_after: None,
+        }
+    }
+
+    /// Returns a discovery error from the balancer if discovery has failed continuously for at
+    /// least `timeout` since its first error.
+    ///
+    /// This only has an effect on policies that [retry](DiscoverPolicy::retry).
+    pub fn fail_after(mut self, timeout: Duration) -> Self {
+        self.fail_after = Some(timeout);
+        self
+    }
+}
+
+// ==== impl Recovery ====
+
+impl Recovery {
+    pub(super) fn new(policy: DiscoverPolicy) -> Self {
+        Self {
+            policy,
+            backoff: None,
+            delay: None,
+            failing_since: None,
+        }
+    }
+
+    /// Waits until discovery may be polled again after an error.
+    pub(super) fn poll_delay(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(delay) = self.delay.as_mut() {
+            futures_core::ready!(delay.as_mut().poll(cx));
+            self.delay = None;
+        }
+        Poll::Ready(())
+    }
+
+    /// Records a successful update from discovery.
+    pub(super) fn on_success(&mut self) {
+        if self.failing_since.take().is_some() {
+            debug!("discovery recovered");
+        }
+        self.backoff = None;
+    }
+
+    /// Records a discovery error, returning it if the balancer should fail.
+    pub(super) fn on_error(&mut self, error: crate::BoxError) -> Result<(), error::Discover> {
+        let maker = match self.policy.backoff.as_mut() {
+            Some(maker) => maker,
+            None => return Err(error::Discover(error)),
+        };
+
+        let now = Instant::now();
+        let since = *self.failing_since.get_or_insert(now);
+        if let Some(timeout) = self.policy.fail_after {
+            if now.saturating_duration_since(since) >= timeout {
+                return Err(error::Discover(error));
+            }
+        }
+
+        warn!(%error, "discovery failed; keeping the last known endpoints");
+        let backoff = self.backoff.get_or_insert_with(|| maker.make_backoff());
+        self.delay = Some(Box::pin(backoff.next_backoff()));
+        Ok(())
+    }
+}
@@ -1,16 +1,19 @@
 use super::super::error;
-use crate::discover::{Change, Discover};
+use super::policy::{DiscoverPolicy, Recovery};
+use crate::discover::{Change, Discover, Metadata, MetadataReceiver};
 use crate::load::Load;
-use crate::ready_cache::{error::Failed, ReadyCache};
+use crate::ready_cache::{cache, error::Failed, ReadyCache};
 use crate::util::rng::{sample_floyd2, HasherRng, Rng};
 use futures_core::ready;
 use futures_util::future::{self, TryFutureExt};
+use std::collections::HashMap;
 use std::hash::Hash;
 use std::marker::PhantomData;
 use std::{
     fmt,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
 use tower_service::Service;
 use tracing::{debug, trace};
@@ -32,15 +35,33 @@ where
     D::Key: Hash,
 {
     discover: D,
+    recovery: Recovery,
 
     services: ReadyCache<D::Key, D::Service, Req>,
     ready_index: Option<usize>,
 
     rng: Box<dyn Rng + Send + Sync>,
 
+    /// The number of times each endpoint was picked, if enabled.
+    picks: Option<HashMap<D::Key, u64>>,
+
+    /// The metadata of each endpoint that has been sent any.
+    metadata: HashMap<D::Key, Metadata>,
+    metadata_rx: Option<MetadataReceiver<D::Key>>,
+
     _req: PhantomData<Req>,
 }
 
+/// A point-in-time view of an endpoint in a [`Balance`].
+///
+/// Returned by [`Balance::snapshot`].
+#[derive(Clone, Debug)]
+pub struct Snapshot<K, M> {
+    endpoint: cache::Snapshot<K, M>,
+    picks: Option<u64>,
+    metadata: Option<Metadata>,
+}
+
 impl<D: Discover, Req> fmt::Debug for Balance<D, Req>
 where
     D: fmt::Debug,
@@ -73,13 +94,55 @@ where
         Self {
             rng,
             discover,
+            recovery: Recovery::new(DiscoverPolicy::default()),
             services: ReadyCache::default(),
             ready_index: None,
+            picks: None,
+            metadata: HashMap::new(),
+            metadata_rx: None,
 
             _req: PhantomData,
         }
     }
 
+    /// Sets how the balancer handles errors from its [`Discover`].
+    ///
+    /// See [`DiscoverPolicy`] for details.
+    pub fn with_discover_policy(mut self, policy: DiscoverPolicy) -> Self {
+        self.recovery = Recovery::new(policy);
+        self
+    }
+
+    /// Fails endpoints that do not become ready within `timeout` of being discovered.
+    ///
+    /// Endpoints that time out are dropped, as if they had failed. Endpoints that are busy
+    /// with requests after they first became ready are not subject to the timeout. See
+    /// [`ReadyCache::with_pending_timeout`] for details.
+    pub fn with_pending_timeout(mut self, timeout: Duration) -> Self {
+        self.services = std::mem::take(&mut self.services).with_pending_timeout(timeout);
+        self
+    }
+
+    /// Receives the [`Metadata`] of endpoints from `metadata`, alongside the [`Discover`].
+    ///
+    /// Endpoints that are draining are only picked over each other. Metadata is received when the
+    /// balancer is polled for readiness. See [`metadata_channel`] for details.
+    ///
+    /// [`metadata_channel`]: crate::discover::metadata_channel
+    pub fn with_metadata(mut self, metadata: MetadataReceiver<D::Key>) -> Self {
+        self.metadata_rx = Some(metadata);
+        self
+    }
+
+    /// Enables counting how many times each endpoint is picked.
+    ///
+    /// The counts are included in [`Balance::snapshot`], and each pick is
+    /// recorded as a `TRACE`-level event along with the endpoint's count.
+    pub fn with_pick_counts(mut self) -> Self {
+        self.picks = Some(HashMap::new());
+        self
+    }
+
     /// Returns the number of endpoints currently tracked by the balancer.
     pub fn len(&self) -> usize {
         self.services.len()
@@ -89,6 +152,78 @@ where
     pub fn is_empty(&self) -> bool {
         self.services.is_empty()
     }
+
+    /// Returns the metadata of the endpoint with the given key, if it has been sent any.
+    ///
+    /// See [`Balance::with_metadata`] for how endpoint metadata is sent.
+    pub fn metadata(&self, key: &D::Key) -> Option<&Metadata> {
+        self.metadata.get(key)
+    }
+
+    /// Sets or clears the metadata of the endpoint with the given key, on behalf of a balancer
+    /// that wraps this one.
+    pub(crate) fn set_metadata(&mut self, key: D::Key, metadata: Option<Metadata>) {
+        match metadata {
+            Some(metadata) => {
+                trace!(draining = metadata.is_draining(), "metadata");
+                self.metadata.insert(key, metadata);
+            }
+            None => {
+                trace!("metadata cleared");
+                self.metadata.remove(&key);
+            }
+        }
+    }
+
+    /// Applies the metadata that was sent since the balancer was last polled.
+    fn update_metadata(&mut self) {
+        while let Some((key, metadata)) = self.metadata_rx.as_mut().and_then(|rx| rx.try_recv()) {
+            self.set_metadata(key, metadata);
+        }
+    }
+
+    /// Returns the number of endpoints that are currently ready.
+    pub(crate) fn ready_len(&self) -> usize {
+        self.services.ready_len()
+    }
+
+    /// Returns a mutable reference to the underlying [`Discover`], so that changes can be fed to it
+    /// by a balancer that wraps this one.
+    pub(crate) fn discover_mut(&mut self) -> &mut D {
+        &mut self.discover
+    }
+}
+
+impl<D, Req> Balance<D, Req>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Load,
+{
+    /// Returns the key, state, current load, and metadata of every endpoint,
+    /// along with how many times it was picked if [pick counts] are enabled.
+    ///
+    /// Ready endpoints are listed before pending endpoints.
+    ///
+    /// [pick counts]: Balance::with_pick_counts
+    pub fn snapshot(&self) -> Vec<Snapshot<D::Key, <D::Service as Load>::Metric>> {
+        self.services
+            .snapshot()
+            .into_iter()
+            .map(|endpoint| {
+                let picks = self
+                    .picks
+                    .as_ref()
+                    .map(|picks| picks.get(endpoint.key()).copied().unwrap_or(0));
+                let metadata = self.metadata.get(endpoint.key()).cloned();
+                Snapshot {
+                    endpoint,
+                    picks,
+                    metadata,
+                }
+            })
+            .collect()
+    }
 }
 
 impl<D, Req> Balance<D, Req>
@@ -103,25 +238,42 @@ where
     /// Polls `discover` for updates, adding new items to `not_ready`.
     ///
     /// Removals may alter the order of either `ready` or `not_ready`.
+    ///
+    /// Errors are handled according to the balancer's [`DiscoverPolicy`].
     fn update_pending_from_discover(
         &mut self,
         cx: &mut Context<'_>,
     ) -> Poll<Option<Result<(), error::Discover>>> {
         debug!("updating from discover");
         loop {
-            match ready!(Pin::new(&mut self.discover).poll_discover(cx))
-                .transpose()
-                .map_err(|e| error::Discover(e.into()))?
-            {
+            // If discovery recently failed, wait before polling it again.
+            ready!(self.recovery.poll_delay(cx));
+
+            let change = match ready!(Pin::new(&mut self.discover).poll_discover(cx)) {
                 None => return Poll::Ready(None),
-                Some(Change::Remove(key)) => {
+                Some(Err(e)) => {
+                    self.recovery.on_error(e.into())?;
+                    continue;
+                }
+                Some(Ok(change)) => {
+                    self.recovery.on_success();
+                    change
+                }
+            };
+
+            match change {
+                Change::Remove(key) => {
                     trace!("remove");
                     self.services.evict(&key);
+                    if let Some(picks) = self.picks.as_mut() {
+                        picks.remove(&key);
+                    }
                 }
-                Some(Change::Insert(key, svc)) => {
+                Change::Insert(key, svc) => {
                     trace!("insert");
                     // If this service already existed in the set, it will be
-                    // replaced as the new one becomes ready.
+                    // replaced as the new one becomes ready. It keeps its
+                    // metadata.
                     self.services.push(key, svc);
                 }
             }
@@ -141,10 +293,11 @@ where
                     debug_assert!(self.services.pending_len() > 0);
                     break;
                 }
-                Poll::Ready(Err(error)) => {
+                Poll::Ready(Err(Failed(key, error))) => {
                     // An individual service was lost; continue processing
                     // pending services.
                     debug!(%error, "dropping failed endpoint");
+                    self.forget_picks_if_lost(&key);
                 }
             }
         }
@@ -168,7 +321,16 @@ where
 
                 let aload = self.ready_index_load(aidx as usize);
                 let bload = self.ready_index_load(bidx as usize);
-                let chosen = if aload <= bload { aidx } else { bidx };
+                // Draining endpoints are only chosen over each other.
+                let chosen = match (
+                    self.ready_index_draining(aidx as usize),
+                    self.ready_index_draining(bidx as usize),
+                ) {
+                    (false, true) => aidx,
+                    (true, false) => bidx,
+                    _ if aload <= bload => aidx,
+                    _ => bidx,
+                };
 
                 trace!(
                     a.index = aidx,
@@ -183,6 +345,68 @@ where
         }
     }
 
+    /// Returns whether the ready endpoint at `index` is draining.
+    fn ready_index_draining(&self, index: usize) -> bool {
+        let (key, _) = self.services.get_ready_index(index).expect("invalid index");
+        self.metadata
+            .get(key)
+            .map_or(false, |metadata| metadata.is_draining())
+    }
+
+    /// Drops an endpoint's pick count if the cache no longer holds a service
+    /// for it.
+    fn forget_picks_if_lost(&mut self, key: &D::Key) {
+        if let Some(picks) = self.picks.as_mut() {
+            if !self.services.pending_contains(key) && self.services.get_ready(key).is_none() {
+                picks.remove(key);
+            }
+        }
+    }
+
+    /// Counts a pick of the ready endpoint at `index`, if pick counts are
+    /// enabled.
+    fn record_pick(&mut self, index: usize) {
+        if let Some(picks) = self.picks.as_mut() {
+            let (key, _) = self.services.get_ready_index(index).expect("invalid index");
+            let count = match picks.get_mut(key) {
+                Some(count) => count,
+                None => picks.entry(key.clone()).or_insert(0),
+            };
+            *count += 1;
+            trace!(index, picks = *count, "picked endpoint");
+        }
+    }
+
+    /// Returns the key of the endpoint selected by the last call to
+    /// `poll_ready`, if any.
+    pub(crate) fn selected_key(&self) -> Option<&D::Key> {
+        let index = self.ready_index?;
+        self.services.get_ready_index(index).map(|(key, _)| key)
+    }
+
+    /// Dispatches `request` to the ready endpoint identified by `key`, instead
+    /// of the endpoint selected by `poll_ready`.
+    ///
+    /// Returns the request if the endpoint is not in the ready set.
+    pub(crate) fn call_key(
+        &mut self,
+        key: &D::Key,
+        request: Req,
+    ) -> Result<<Self as Service<Req>>::Future, Req> {
+        let index = match self.services.get_ready(key) {
+            Some((index, _, _)) => index,
+            None => return Err(request),
+        };
+        // Dispatching perturbs the order of the ready set, so the selected
+        // index can no longer be used.
+        self.ready_index = None;
+        self.record_pick(index);
+        Ok(self
+            .services
+            .call_ready_index(index, request)
+            .map_err(Into::into))
+    }
+
     /// Accesses a ready endpoint by index and returns its current load.
     fn ready_index_load(&self, index: usize) -> <D::Service as Load>::Metric {
         let (_, svc) = self.services.get_ready_index(index).expect("invalid index");
@@ -210,6 +434,7 @@ where
         // `ready_index` may have already been set by a prior invocation. These
         // updates cannot disturb the order of existing ready services.
         let _ = self.update_pending_from_discover(cx)?;
+        self.update_metadata();
         self.promote_pending_to_ready(cx);
 
         loop {
@@ -230,10 +455,11 @@ where
                         // The service is no longer ready. Try to find a new one.
                         trace!("ready service became unavailable");
                     }
-                    Err(Failed(_, error)) => {
+                    Err(Failed(key, error)) => {
                         // The ready endpoint failed, so log the error and try
                         // to find a new one.
                         debug!(%error, "endpoint failed");
+                        self.forget_picks_if_lost(&key);
                     }
                 }
             }
@@ -252,8 +478,41 @@ where
 
     fn call(&mut self, request: Req) -> Self::Future {
         let index = self.ready_index.take().expect("called before ready");
+        self.record_pick(index);
         self.services
             .call_ready_index(index, request)
             .map_err(Into::into)
     }
 }
+
+// === impl Snapshot ===
+
+impl<K, M> Snapshot<K, M> {
+    /// Returns the endpoint's key.
+    pub fn key(&self) -> &K {
+        self.endpoint.key()
+    }
+
+    /// Returns whether the endpoint was ready or pending.
+    pub fn state(&self) -> cache::State {
+        self.endpoint.state()
+    }
+
+    /// Returns the endpoint's load.
+    pub fn load(&self) -> &M {
+        self.endpoint.load()
+    }
+
+    /// Returns how many times the endpoint was picked, if [pick counts] are
+    /// enabled.
+    ///
+    /// [pick counts]: Balance::with_pick_counts
+    pub fn picks(&self) -> Option<u64> {
+        self.picks
+    }
+
+    /// Returns the endpoint's metadata, if it has been sent any.
+    pub fn metadata(&self) -> Option<&Metadata> {
+        self.metadata.as_ref()
+    }
+}
@@ -1,5 +1,6 @@
 use crate::discover::ServiceList;
 use crate::load;
+use crate::ready_cache::cache::State;
 use futures_util::pin_mut;
 use std::task::Poll;
 use tokio_test::{assert_pending, assert_ready, assert_ready_ok, task};
@@ -123,3 +124,88 @@ async fn two_endpoints_with_equal_load() {
         "balancer must drop failed endpoints",
     );
 }
+
+#[tokio::test]
+async fn snapshot_counts_picks() {
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    let mock_a = load::Constant::new(mock_a, 1);
+    let mock_b = load::Constant::new(mock_b, 2);
+
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b].into_iter());
+    let mut svc = mock::Spawn::new(Balance::new(disco).with_pick_counts());
+
+    handle_a.allow(1);
+    handle_b.allow(0);
+    assert_ready_ok!(svc.poll_ready());
+
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response("a");
+    assert_eq!(assert_ready_ok!(fut.poll()), "a");
+
+    let mut snapshot = svc.get_ref().snapshot();
+    snapshot.sort_by_key(|endpoint| *endpoint.key());
+    let endpoints = snapshot
+        .iter()
+        .map(|e| (*e.key(), e.state(), *e.load(), e.picks()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        endpoints,
+        vec![
+            (0, State::Pending, 1, Some(1)),
+            (1, State::Pending, 2, Some(0)),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn avoids_draining_endpoints() {
+    use crate::discover::{metadata_channel, Change, Metadata};
+    use futures_util::stream;
+    use std::convert::Infallible;
+
+    let (mock_a, handle_a) = mock::pair();
+    let (mock_b, handle_b) = mock::pair();
+    let mock_a = load::Constant::new(mock_a, 1);
+    let mock_b = load::Constant::new(mock_b, 2);
+
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let changes = vec![Change::Insert(0, mock_a), Change::Insert(1, mock_b)];
+    let disco = stream::iter(changes.into_iter().map(Ok::<_, Infallible>));
+    let (metadata_tx, metadata_rx) = metadata_channel();
+    metadata_tx.send(0, Metadata::new().with_draining(true));
+    let mut svc = mock::Spawn::new(Balance::new(disco).with_metadata(metadata_rx));
+
+    handle_a.allow(1);
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    assert!(svc.get_ref().metadata(&0).unwrap().is_draining());
+    assert!(svc.get_ref().metadata(&1).is_none());
+
+    // The less loaded endpoint is draining, so the other is picked.
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_b, ()).send_response("b");
+    assert_eq!(assert_ready_ok!(fut.poll()), "b");
+
+    let snapshot = svc.get_ref().snapshot();
+    let draining = snapshot
+        .iter()
+        .find(|endpoint| *endpoint.key() == 0)
+        .and_then(|endpoint| endpoint.metadata())
+        .map(|metadata| metadata.is_draining());
+    assert_eq!(draining, Some(true));
+
+    // Once its metadata is cleared, the less loaded endpoint is picked again.
+    metadata_tx.clear(0);
+    handle_b.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    assert!(svc.get_ref().metadata(&0).is_none());
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response("a");
+    assert_eq!(assert_ready_ok!(fut.poll()), "a");
+}
@@ -0,0 +1,48 @@
+use super::Balance;
+use crate::balance::make::{self, NewBalance};
+use crate::discover::Discover;
+use std::fmt;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use tower_service::Service;
+
+/// Selects the round-robin [`Balance`] as the balancer built by [`MakeBalance`] and
+/// [`MakeBalanceLayer`].
+pub struct RoundRobin<Req> {
+    _marker: PhantomData<fn(Req)>,
+}
+
+/// Constructs round-robin load balancers over dynamic service sets produced by a wrapped "inner"
+/// service.
+///
+/// See [`make::MakeBalance`] for details.
+pub type MakeBalance<S, Req> = make::MakeBalance<S, RoundRobin<Req>>;
+
+/// A round-robin [`Balance`] in the making.
+pub type MakeFuture<F, Req> = make::MakeFuture<F, RoundRobin<Req>>;
+
+/// Construct round-robin load balancers over dynamic service sets produced by the "inner" service in
+/// response to requests coming from the "outer" service.
+///
+/// See [`make::MakeBalanceLayer`] for details.
+pub type MakeBalanceLayer<D, Req> = make::MakeBalanceLayer<D, RoundRobin<Req>>;
+
+impl<D, Req> NewBalance<D> for RoundRobin<Req>
+where
+    D: Discover,
+    D::Key: Hash,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    type Balance = Balance<D, Req>;
+
+    fn new_balance(discover: D) -> Self::Balance {
+        Balance::new(discover)
+    }
+}
+
+impl<Req> fmt::Debug for RoundRobin<Req> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RoundRobin").finish()
+    }
+}
@@ -0,0 +1,25 @@
+//! This module implements a round-robin load balancer.
+//!
+//! Whenever a request comes in, the balancer sends it to the next ready service in a fixed
+//! rotation over the service set, skipping services that are not ready. Unlike
+//! [`p2c`](crate::balance::p2c), the choice does not depend on randomness or on any
+//! [`Load`](crate::load::Load) measurement, so every service receives an equal share of the
+//! requests as long as all of them are ready. This makes it a good fit for small sets of similar
+//! services, where random sampling would distribute requests unevenly in the short term.
+//!
+//! The balance service and layer implementations rely on _service discovery_ to provide the
+//! underlying set of services to balance requests across. This happens through the
+//! [`Discover`](crate::discover::Discover) trait, which is essentially a [`Stream`] that indicates
+//! when services become available or go away. If you have a fixed set of services, consider using
+//! [`ServiceList`](crate::discover::ServiceList).
+//!
+//! [`Stream`]: https://docs.rs/futures/0.3/futures/stream/trait.Stream.html
+
+mod make;
+mod service;
+
+#[cfg(test)]
+mod test;
+
+pub use make::{MakeBalance, MakeBalanceLayer, MakeFuture, RoundRobin};
+pub use service::Balance;
@@ -0,0 +1,252 @@
+use super::super::error;
+use crate::discover::{Change, Discover};
+use crate::ready_cache::{error::Failed, ReadyCache};
+use futures_core::ready;
+use futures_util::future::{self, TryFutureExt};
+use indexmap::IndexSet;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// Distributes requests across services in a fixed rotation.
+///
+/// See the [module-level documentation](..) for details.
+///
+/// Note that [`Balance`] requires that the [`Discover`] you use is [`Unpin`] in order to implement
+/// [`Service`]. This is because it needs to be accessed from [`Service::poll_ready`], which takes
+/// `&mut self`. You can achieve this easily by wrapping your [`Discover`] in [`Box::pin`] before you
+/// construct the [`Balance`] instance. For more details, see [#319].
+///
+/// [`Box::pin`]: std::boxed::Box::pin()
+/// [#319]: https://github.com/tower-rs/tower/issues/319
+pub struct Balance<D, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+{
+    discover: D,
+
+    services: ReadyCache<D::Key, D::Service, Req>,
+
+    /// The rotation order, in which endpoints were first discovered.
+    rotation: IndexSet<D::Key>,
+    /// The position in `rotation` at which to look for the next ready endpoint.
+    next: usize,
+    ready_key: Option<D::Key>,
+
+    _req: PhantomData<Req>,
+}
+
+impl<D: Discover, Req> fmt::Debug for Balance<D, Req>
+where
+    D: fmt::Debug,
+    D::Key: Hash + fmt::Debug,
+    D::Service: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Balance")
+            .field("discover", &self.discover)
+            .field("services", &self.services)
+            .finish()
+    }
+}
+
+impl<D, Req> Balance<D, Req>
+where
+    D: Discover,
+    D::Key: Hash,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Constructs a round-robin load balancer.
+    pub fn new(discover: D) -> Self {
+        Self {
+            discover,
+            services: ReadyCache::default(),
+            rotation: IndexSet::default(),
+            next: 0,
+            ready_key: None,
+
+            _req: PhantomData,
+        }
+    }
+
+    /// Returns the number of endpoints currently tracked by the balancer.
+    pub fn len(&self) -> usize {
+        self.services.len()
+    }
+
+    /// Returns whether or not the balancer is empty.
+    pub fn is_empty(&self) -> bool {
+        self.services.is_empty()
+    }
+}
+
+impl<D, Req> Balance<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    /// Polls `discover` for updates, adding new items to `not_ready`.
+    fn update_pending_from_discover(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<(), error::Discover>>> {
+        debug!("updating from discover");
+        loop {
+            match ready!(Pin::new(&mut self.discover).poll_discover(cx))
+                .transpose()
+                .map_err(|e| error::Discover(e.into()))?
+            {
+                None => return Poll::Ready(None),
+                Some(Change::Remove(key)) => {
+                    trace!("remove");
+                    self.services.evict(&key);
+                    self.remove_from_rotation(&key);
+                }
+                Some(Change::Insert(key, svc)) => {
+                    trace!("insert");
+                    // If this service already existed in the set, it will be
+                    // replaced as the new one becomes ready. It keeps its
+                    // position in the rotation.
+                    self.rotation.insert(key.clone());
+                    self.services.push(key, svc);
+                }
+            }
+        }
+    }
+
+    fn promote_pending_to_ready(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.services.poll_pending(cx) {
+                Poll::Ready(Ok(())) => {
+                    // There are no remaining pending services.
+                    debug_assert_eq!(self.services.pending_len(), 0);
+                    break;
+                }
+                Poll::Pending => {
+                    // None of the pending services are ready.
+                    debug_assert!(self.services.pending_len() > 0);
+                    break;
+                }
+                Poll::Ready(Err(Failed(key, error))) => {
+                    // An individual service was lost; continue processing
+                    // pending services.
+                    debug!(%error, "dropping failed endpoint");
+                    self.forget_if_lost(&key);
+                }
+            }
+        }
+        trace!(
+            ready = %self.services.ready_len(),
+            pending = %self.services.pending_len(),
+            "poll_unready"
+        );
+    }
+
+    /// Returns the next endpoint in the rotation that is in the ready set, and
+    /// advances the rotation past it.
+    fn next_ready_key(&mut self) -> Option<D::Key> {
+        let len = self.rotation.len();
+        for offset in 0..len {
+            let index = (self.next + offset) % len;
+            let key = &self.rotation[index];
+            if self.services.get_ready(key).is_some() {
+                trace!(index, "round robin");
+                self.next = index + 1;
+                return Some(key.clone());
+            }
+        }
+        None
+    }
+
+    /// Removes an endpoint from the rotation if the cache no longer holds a
+    /// service for it.
+    ///
+    /// A failed service may have been replacing a service that is still ready,
+    /// in which case the endpoint keeps its position.
+    fn forget_if_lost(&mut self, key: &D::Key) {
+        if !self.services.pending_contains(key) && self.services.get_ready(key).is_none() {
+            self.remove_from_rotation(key);
+        }
+    }
+
+    fn remove_from_rotation(&mut self, key: &D::Key) {
+        if let Some(index) = self.rotation.shift_remove_full(key).map(|(i, _)| i) {
+            // Keep pointing at the endpoint that follows the removed one.
+            if index < self.next {
+                self.next -= 1;
+            }
+        }
+    }
+}
+
+impl<D, Req> Service<Req> for Balance<D, Req>
+where
+    D: Discover + Unpin,
+    D::Key: Hash + Clone,
+    D::Error: Into<crate::BoxError>,
+    D::Service: Service<Req>,
+    <D::Service as Service<Req>>::Error: Into<crate::BoxError>,
+{
+    type Response = <D::Service as Service<Req>>::Response;
+    type Error = crate::BoxError;
+    type Future = future::MapErr<
+        <D::Service as Service<Req>>::Future,
+        fn(<D::Service as Service<Req>>::Error) -> crate::BoxError,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        let _ = self.update_pending_from_discover(cx)?;
+        self.promote_pending_to_ready(cx);
+
+        loop {
+            // If a service has already been selected, ensure that it is ready.
+            // This ensures that the underlying service is ready immediately
+            // before a request is dispatched to it (i.e. in the same task
+            // invocation).
+            if let Some(key) = self.ready_key.take() {
+                match self.services.check_ready(cx, &key) {
+                    Ok(true) => {
+                        // The service remains ready.
+                        self.ready_key = Some(key);
+                        return Poll::Ready(Ok(()));
+                    }
+                    Ok(false) => {
+                        // The service is no longer ready. Try to find a new one.
+                        trace!("ready service became unavailable");
+                    }
+                    Err(Failed(_, error)) => {
+                        // The ready endpoint failed, so log the error and try
+                        // to find a new one.
+                        debug!(%error, "endpoint failed");
+                        self.forget_if_lost(&key);
+                    }
+                }
+            }
+
+            // Select the next ready service in the rotation.
+            self.ready_key = self.next_ready_key();
+            if self.ready_key.is_none() {
+                debug_assert_eq!(self.services.ready_len(), 0);
+                // We have previously registered interest in updates from
+                // discover and pending services.
+                return Poll::Pending;
+            }
+        }
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        let key = self.ready_key.take().expect("called before ready");
+        self.services.call_ready(&key, request).map_err(Into::into)
+    }
+}
@@ -0,0 +1,79 @@
+use crate::discover::ServiceList;
+use futures_util::pin_mut;
+use tokio_test::{assert_pending, assert_ready_ok, task};
+use tower_test::{assert_request_eq, mock};
+
+use super::*;
+
+#[tokio::test]
+async fn empty() {
+    let empty: Vec<mock::Mock<(), char>> = vec![];
+    let disco = ServiceList::new(empty);
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+    assert_pending!(svc.poll_ready());
+}
+
+#[tokio::test]
+async fn rotates_through_ready_endpoints() {
+    let (mock_a, handle_a) = mock::pair::<(), char>();
+    let (mock_b, handle_b) = mock::pair::<(), char>();
+    let (mock_c, handle_c) = mock::pair::<(), char>();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+    pin_mut!(handle_c);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b, mock_c]);
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    for expected in "abcabc".chars() {
+        handle_a.allow(1);
+        handle_b.allow(1);
+        handle_c.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+
+        let mut fut = task::spawn(svc.call(()));
+        let mut handle = match expected {
+            'a' => handle_a.as_mut(),
+            'b' => handle_b.as_mut(),
+            _ => handle_c.as_mut(),
+        };
+        assert_request_eq!(handle, ()).send_response(expected);
+        assert_eq!(assert_ready_ok!(fut.poll()), expected);
+    }
+}
+
+#[tokio::test]
+async fn skips_unready_endpoints() {
+    let (mock_a, handle_a) = mock::pair::<(), char>();
+    let (mock_b, handle_b) = mock::pair::<(), char>();
+    pin_mut!(handle_a);
+    pin_mut!(handle_b);
+
+    let disco = ServiceList::new(vec![mock_a, mock_b]);
+    let mut svc = mock::Spawn::new(Balance::new(disco));
+
+    handle_a.allow(0);
+    handle_b.allow(0);
+    assert_pending!(svc.poll_ready());
+    assert_eq!(svc.get_ref().len(), 2);
+
+    for _ in 0..3 {
+        handle_b.allow(1);
+        assert_ready_ok!(svc.poll_ready());
+        let mut fut = task::spawn(svc.call(()));
+        assert_request_eq!(handle_b, ()).send_response('b');
+        assert_eq!(assert_ready_ok!(fut.poll()), 'b');
+    }
+
+    handle_b.send_error("endpoint lost");
+    handle_a.allow(1);
+    assert_ready_ok!(svc.poll_ready());
+    assert_eq!(
+        svc.get_ref().len(),
+        1,
+        "balancer must drop failed endpoints"
+    );
+    let mut fut = task::spawn(svc.call(()));
+    assert_request_eq!(handle_a, ()).send_response('a');
+    assert_eq!(assert_ready_ok!(fut.poll()), 'a');
+}
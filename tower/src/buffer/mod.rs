@@ -45,3 +45,5 @@ mod worker;
 
 pub use self::layer::BufferLayer;
 pub use self::service::Buffer;
+#[cfg(feature = "load")]
+pub use self::service::Cost;
@@ -79,6 +79,54 @@ where
         (buffer, worker)
     }
 
+    /// Creates a new [`Buffer`] wrapping `service`, whose [`Load`] includes the load of
+    /// `service`.
+    ///
+    /// The buffer's worker measures the load of `service` whenever it dispatches a request to it,
+    /// so the load reported by the [`Buffer`] may lag behind that of the service. See
+    /// [`Buffer::new`] for the meaning of `bound`.
+    ///
+    /// [`Load`]: crate::load::Load
+    #[cfg(feature = "load")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "load")))]
+    pub fn with_load<S>(service: S, bound: usize) -> Self
+    where
+        S: Service<Req, Future = F> + crate::load::Load + Send + 'static,
+        S::Metric: Into<f64>,
+        F: Send,
+        S::Error: Into<crate::BoxError> + Send + Sync,
+        Req: Send + 'static,
+    {
+        let (service, worker) = Self::pair_with_load(service, bound);
+        tokio::spawn(worker);
+        service
+    }
+
+    /// Creates a new [`Buffer`] wrapping `service`, whose [`Load`] includes the load of
+    /// `service`, but returns the background worker.
+    ///
+    /// See [`Buffer::with_load`] and [`Buffer::pair`] for details.
+    ///
+    /// [`Load`]: crate::load::Load
+    #[cfg(feature = "load")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "load")))]
+    pub fn pair_with_load<S>(service: S, bound: usize) -> (Self, Worker<S, Req>)
+    where
+        S: Service<Req, Future = F> + crate::load::Load + Send + 'static,
+        S::Metric: Into<f64>,
+        F: Send,
+        S::Error: Into<crate::BoxError> + Send + Sync,
+        Req: Send + 'static,
+    {
+        let (tx, rx) = mpsc::channel(bound);
+        let (handle, worker) = Worker::with_load(service, rx, |svc| svc.load().into());
+        let buffer = Self {
+            tx: PollSender::new(tx),
+            handle,
+        };
+        (buffer, worker)
+    }
+
     fn get_worker_error(&self) -> crate::BoxError {
         self.handle.get_error_on_closed()
     }
@@ -119,17 +167,64 @@ where
         // acquired, so we can freely allocate a oneshot.
         let (tx, rx) = oneshot::channel();
 
+        // Count the request before sending it, so that the worker can't dequeue it first.
+        #[cfg(feature = "load")]
+        self.handle.enqueued();
+
         match self.tx.send_item(Message { request, span, tx }) {
             Ok(_) => ResponseFuture::new(rx),
             // If the channel is closed, propagate the error from the worker.
             Err(_) => {
                 tracing::trace!("buffer channel closed");
+                #[cfg(feature = "load")]
+                self.handle.dequeued();
                 ResponseFuture::failed(self.get_worker_error())
             }
         }
     }
 }
 
+/// Reports the number of requests waiting in the buffer, and the load of the inner service if the
+/// buffer was constructed with [`Buffer::with_load`].
+#[cfg(feature = "load")]
+#[cfg_attr(docsrs, doc(cfg(feature = "load")))]
+impl<Req, F> crate::load::Load for Buffer<Req, F> {
+    type Metric = Cost;
+
+    fn load(&self) -> Cost {
+        Cost {
+            queued: self.handle.queued(),
+            inner: self.handle.inner_load(),
+        }
+    }
+}
+
+/// The load of a [`Buffer`].
+///
+/// Loads are compared first by the number of requests waiting in the buffer, and then by the load
+/// of the inner service.
+#[cfg(feature = "load")]
+#[cfg_attr(docsrs, doc(cfg(feature = "load")))]
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Cost {
+    queued: usize,
+    inner: f64,
+}
+
+#[cfg(feature = "load")]
+impl Cost {
+    /// Returns the number of requests that were waiting in the buffer.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    /// Returns the load of the inner service, or zero if the buffer was not constructed with
+    /// [`Buffer::with_load`].
+    pub fn inner(&self) -> f64 {
+        self.inner
+    }
+}
+
 impl<Req, F> Clone for Buffer<Req, F>
 where
     Req: Send + 'static,
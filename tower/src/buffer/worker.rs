@@ -3,6 +3,8 @@ use super::{
     message::Message,
 };
 use futures_core::ready;
+#[cfg(feature = "load")]
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
 use std::{
     future::Future,
@@ -31,13 +33,32 @@ pin_project_lite::pin_project! {
         finish: bool,
         failed: Option<ServiceError>,
         handle: Handle,
+        measure_load: MeasureLoad<T>,
     }
 }
 
+/// Measures the load of the service, if the buffer reports it.
+#[cfg(feature = "load")]
+type MeasureLoad<T> = Option<fn(&T) -> f64>;
+#[cfg(not(feature = "load"))]
+type MeasureLoad<T> = std::marker::PhantomData<fn(&T)>;
+
 /// Get the error out
 #[derive(Debug)]
 pub(crate) struct Handle {
     inner: Arc<Mutex<Option<ServiceError>>>,
+    #[cfg(feature = "load")]
+    load: Arc<SharedLoad>,
+}
+
+/// The load of a buffer, shared between its handles and its worker.
+#[cfg(feature = "load")]
+#[derive(Debug, Default)]
+struct SharedLoad {
+    /// The number of requests in the channel.
+    queued: AtomicUsize,
+    /// The bits of the inner service's most recently measured load.
+    inner: AtomicU64,
 }
 
 impl<T, Request> Worker<T, Request>
@@ -51,6 +72,8 @@ where
     ) -> (Handle, Worker<T, Request>) {
         let handle = Handle {
             inner: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "load")]
+            load: Arc::new(SharedLoad::default()),
         };
 
         let worker = Worker {
@@ -60,11 +83,37 @@ where
             rx,
             service,
             handle: handle.clone(),
+            measure_load: Default::default(),
         };
 
         (handle, worker)
     }
 
+    /// Like [`Worker::new`], but the worker also publishes the load of the service to the
+    /// handle, measured with `measure_load`, whenever it dispatches a request.
+    #[cfg(feature = "load")]
+    pub(crate) fn with_load(
+        service: T,
+        rx: mpsc::Receiver<Message<Request, T::Future>>,
+        measure_load: fn(&T) -> f64,
+    ) -> (Handle, Worker<T, Request>) {
+        let (handle, mut worker) = Self::new(service, rx);
+        worker.measure_load = Some(measure_load);
+        worker.publish_load();
+        (handle, worker)
+    }
+
+    #[cfg(feature = "load")]
+    fn publish_load(&self) {
+        if let Some(measure_load) = self.measure_load {
+            let load = measure_load(&self.service);
+            self.handle
+                .load
+                .inner
+                .store(load.to_bits(), Ordering::Relaxed);
+        }
+    }
+
     /// Return the next queued Message that hasn't been canceled.
     ///
     /// If a `Message` is returned, the `bool` is true if this is the first time we received this
@@ -93,6 +142,9 @@ where
 
         // Get the next request
         while let Some(msg) = ready!(Pin::new(&mut self.rx).poll_recv(cx)) {
+            #[cfg(feature = "load")]
+            self.handle.load.queued.fetch_sub(1, Ordering::Relaxed);
+
             if !msg.tx.is_closed() {
                 tracing::trace!("processing new request");
                 return Poll::Ready(Some((msg, true)));
@@ -169,6 +221,8 @@ where
                         Poll::Ready(Ok(())) => {
                             tracing::debug!(service.ready = true, message = "processing request");
                             let response = self.service.call(msg.request);
+                            #[cfg(feature = "load")]
+                            self.publish_load();
 
                             // Send the response future back to the sender.
                             //
@@ -216,12 +270,38 @@ impl Handle {
             .map(|svc_err| svc_err.clone().into())
             .unwrap_or_else(|| Closed::new().into())
     }
+
+    /// Records that a request is about to be sent to the worker.
+    #[cfg(feature = "load")]
+    pub(crate) fn enqueued(&self) {
+        self.load.queued.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Records that a request could not be sent to the worker after all.
+    #[cfg(feature = "load")]
+    pub(crate) fn dequeued(&self) {
+        self.load.queued.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of requests in the channel.
+    #[cfg(feature = "load")]
+    pub(crate) fn queued(&self) -> usize {
+        self.load.queued.load(Ordering::Relaxed)
+    }
+
+    /// Returns the most recently published load of the inner service.
+    #[cfg(feature = "load")]
+    pub(crate) fn inner_load(&self) -> f64 {
+        f64::from_bits(self.load.inner.load(Ordering::Relaxed))
+    }
 }
 
 impl Clone for Handle {
     fn clone(&self) -> Handle {
         Handle {
             inner: self.inner.clone(),
+            #[cfg(feature = "load")]
+            load: self.load.clone(),
         }
     }
 }
@@ -0,0 +1,394 @@
+use super::{Change, Discover};
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep, sleep_until, Instant, Sleep};
+use tracing::trace;
+
+pin_project! {
+    /// Coalesces bursts of changes from a [`Discover`].
+    ///
+    /// Service registries sometimes flap, yielding bursts of changes that would make a balancer
+    /// insert and evict the same services over and over. [`Debounce`] collects the changes that
+    /// `discover` yields over a window, starting from the first change after a quiet period, and
+    /// then passes on the net effect of those changes:
+    ///
+    /// - A service that is inserted and then removed within the window is never passed on.
+    /// - A service that is removed and then inserted again within the window is passed on as a
+    ///   single [`Change::Insert`], which replaces the existing service.
+    ///
+    /// Optionally, removals can be delayed with [`Debounce::with_removal_delay`], so that requests
+    /// that are in flight to a removed service can complete before it is evicted. The removal is
+    /// called off if the service is inserted again during the delay. To steer new requests away
+    /// from a service while its removal is delayed, mark it as draining through a
+    /// [`metadata_channel`].
+    ///
+    /// [`metadata_channel`]: crate::discover::metadata_channel
+    ///
+    /// Errors from `discover` are passed on immediately.
+    pub struct Debounce<D>
+    where
+        D: Discover,
+    {
+        #[pin]
+        discover: D,
+        discover_done: bool,
+        state: State<D::Key, D::Service>,
+    }
+}
+
+struct State<K, S> {
+    window: Duration,
+    removal_delay: Option<Duration>,
+    // The end of the current window, if any changes are pending.
+    window_sleep: Option<Pin<Box<Sleep>>>,
+    // Keys with pending changes, in the order that they were first changed. Keys whose pending
+    // changes cancelled out may be listed, and keys may be listed more than once.
+    order: VecDeque<K>,
+    pending: HashMap<K, Net<S>>,
+    // Keys of services that have been passed on and not yet removed.
+    inserted: HashSet<K>,
+    draining: Draining<K>,
+    ready: VecDeque<Change<K, S>>,
+}
+
+/// The net effect of the pending changes to a key.
+enum Net<S> {
+    Insert(S),
+    Remove,
+}
+
+/// Services whose removal has been delayed.
+struct Draining<K> {
+    // The deadline of each removal, in the order that they were delayed.
+    queue: VecDeque<(Instant, K, u64)>,
+    // The generation of the current removal of each key, so that cancelled removals are skipped.
+    generations: HashMap<K, u64>,
+    next_generation: u64,
+    sleep: Option<Pin<Box<Sleep>>>,
+}
+
+impl<D> Debounce<D>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+{
+    /// Coalesces the changes yielded by `discover` over windows of length `window`.
+    pub fn new(discover: D, window: Duration) -> Self {
+        Self {
+            discover,
+            discover_done: false,
+            state: State {
+                window,
+                removal_delay: None,
+                window_sleep: None,
+                order: VecDeque::new(),
+                pending: HashMap::new(),
+                inserted: HashSet::new(),
+                draining: Draining {
+                    queue: VecDeque::new(),
+                    generations: HashMap::new(),
+                    next_generation: 0,
+                    sleep: None,
+                },
+                ready: VecDeque::new(),
+            },
+        }
+    }
+
+    /// Delays the removal of services by `delay`, after the window in which they are removed.
+    pub fn with_removal_delay(mut self, delay: Duration) -> Self {
+        self.state.removal_delay = Some(delay);
+        self
+    }
+}
+
+impl<D> fmt::Debug for Debounce<D>
+where
+    D: Discover + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Debounce")
+            .field("discover", &self.discover)
+            .field("window", &self.state.window)
+            .field("removal_delay", &self.state.removal_delay)
+            .field("pending", &self.state.pending.len())
+            .field("draining", &self.state.draining.generations.len())
+            .finish()
+    }
+}
+
+impl<D> Stream for Debounce<D>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+{
+    type Item = Result<Change<D::Key, D::Service>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let state = this.state;
+        loop {
+            if let Some(change) = state.ready.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            while !*this.discover_done {
+                match this.discover.as_mut().poll_discover(cx) {
+                    Poll::Ready(Some(Ok(change))) => state.absorb(change),
+                    Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                    Poll::Ready(None) => *this.discover_done = true,
+                    Poll::Pending => break,
+                }
+            }
+
+            if *this.discover_done {
+                // Pass on everything that's left, without waiting.
+                state.removal_delay = None;
+                state.flush();
+                state.drain(None);
+                match state.ready.pop_front() {
+                    Some(change) => return Poll::Ready(Some(Ok(change))),
+                    None => return Poll::Ready(None),
+                }
+            }
+
+            if let Some(sleep) = state.window_sleep.as_mut() {
+                if sleep.as_mut().poll(cx).is_ready() {
+                    state.flush();
+                    continue;
+                }
+            }
+
+            if let Some(sleep) = state.draining.sleep.as_mut() {
+                if sleep.as_mut().poll(cx).is_ready() {
+                    state.drain(Some(Instant::now()));
+                    continue;
+                }
+            }
+
+            return Poll::Pending;
+        }
+    }
+}
+
+// ===== impl State =====
+
+impl<K, S> State<K, S>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Folds `change` into the pending changes, starting a window if there is none.
+    fn absorb(&mut self, change: Change<K, S>) {
+        if self.window_sleep.is_none() {
+            self.window_sleep = Some(Box::pin(sleep(self.window)));
+        }
+
+        match change {
+            Change::Insert(key, service) => {
+                if self
+                    .pending
+                    .insert(key.clone(), Net::Insert(service))
+                    .is_none()
+                {
+                    self.order.push_back(key);
+                }
+            }
+            Change::Remove(key) => {
+                if self.inserted.contains(&key) {
+                    if self.pending.insert(key.clone(), Net::Remove).is_none() {
+                        self.order.push_back(key);
+                    }
+                } else {
+                    // The service was never passed on, so the insert and remove cancel out.
+                    trace!("insert cancelled");
+                    self.pending.remove(&key);
+                }
+            }
+        }
+    }
+
+    /// Passes on the net effect of the pending changes.
+    fn flush(&mut self) {
+        self.window_sleep = None;
+        while let Some(key) = self.order.pop_front() {
+            let net = match self.pending.remove(&key) {
+                Some(net) => net,
+                None => continue,
+            };
+            match net {
+                Net::Insert(service) => {
+                    // A service that replaces a draining one calls off its removal.
+                    self.draining.generations.remove(&key);
+                    self.inserted.insert(key.clone());
+                    self.ready.push_back(Change::Insert(key, service));
+                }
+                Net::Remove if self.draining.generations.contains_key(&key) => {}
+                Net::Remove => match self.removal_delay {
+                    Some(delay) => {
+                        trace!(?delay, "delaying removal");
+                        self.draining.delay(key, delay);
+                    }
+                    None => {
+                        self.inserted.remove(&key);
+                        self.ready.push_back(Change::Remove(key));
+                    }
+                },
+            }
+        }
+    }
+
+    /// Passes on the delayed removals that are due by `now`, or all of them if `now` is `None`.
+    fn drain(&mut self, now: Option<Instant>) {
+        while let Some((deadline, _, _)) = self.draining.queue.front() {
+            if now.map_or(false, |now| *deadline > now) {
+                break;
+            }
+            let (_, key, generation) = self.draining.queue.pop_front().expect("must be present");
+            if self.draining.generations.get(&key) == Some(&generation) {
+                trace!("removing drained service");
+                self.draining.generations.remove(&key);
+                self.inserted.remove(&key);
+                self.ready.push_back(Change::Remove(key));
+            }
+        }
+        self.draining.reset_sleep();
+    }
+}
+
+// ===== impl Draining =====
+
+impl<K> Draining<K>
+where
+    K: Hash + Eq + Clone,
+{
+    /// Schedules the removal of `key` after `delay`.
+    fn delay(&mut self, key: K, delay: Duration) {
+        self.next_generation += 1;
+        self.generations.insert(key.clone(), self.next_generation);
+        self.queue
+            .push_back((Instant::now() + delay, key, self.next_generation));
+        self.reset_sleep();
+    }
+
+    /// Points the sleep at the earliest pending removal.
+    fn reset_sleep(&mut self) {
+        match (self.queue.front(), self.sleep.as_mut()) {
+            (None, _) => self.sleep = None,
+            (Some((deadline, _, _)), Some(sleep)) => sleep.as_mut().reset(*deadline),
+            (Some((deadline, _, _)), None) => self.sleep = Some(Box::pin(sleep_until(*deadline))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use tokio::sync::mpsc;
+    use tokio::time;
+    use tokio_test::{assert_pending, assert_ready, task};
+
+    use super::*;
+
+    type Changes = mpsc::UnboundedSender<Result<Change<usize, &'static str>, Infallible>>;
+
+    /// A discover that yields the changes sent to it.
+    struct Channel(mpsc::UnboundedReceiver<Result<Change<usize, &'static str>, Infallible>>);
+
+    impl Stream for Channel {
+        type Item = Result<Change<usize, &'static str>, Infallible>;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            self.0.poll_recv(cx)
+        }
+    }
+
+    fn channel() -> (Changes, Channel) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (tx, Channel(rx))
+    }
+
+    fn describe(change: Change<usize, &'static str>) -> String {
+        match change {
+            Change::Insert(key, service) => format!("+{}={}", key, service),
+            Change::Remove(key) => format!("-{}", key),
+        }
+    }
+
+    fn changes(debounce: &mut task::Spawn<Debounce<Channel>>) -> Vec<String> {
+        let mut changes = Vec::new();
+        while let Poll::Ready(Some(change)) = debounce.poll_next() {
+            changes.push(describe(change.unwrap()));
+        }
+        changes
+    }
+
+    #[tokio::test]
+    async fn coalesces_changes() {
+        time::pause();
+
+        let (tx, rx) = channel();
+        let mut debounce = task::spawn(Debounce::new(rx, Duration::from_secs(1)));
+
+        tx.send(Ok(Change::Insert(0, "a"))).unwrap();
+        tx.send(Ok(Change::Insert(1, "b"))).unwrap();
+        tx.send(Ok(Change::Remove(1))).unwrap();
+        assert_pending!(debounce.poll_next());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut debounce), ["+0=a"]);
+
+        // A removal followed by an insert replaces the service.
+        tx.send(Ok(Change::Remove(0))).unwrap();
+        tx.send(Ok(Change::Insert(0, "c"))).unwrap();
+        assert_pending!(debounce.poll_next());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut debounce), ["+0=c"]);
+
+        tx.send(Ok(Change::Remove(0))).unwrap();
+        drop(tx);
+        assert_eq!(changes(&mut debounce), ["-0"]);
+        assert!(assert_ready!(debounce.poll_next()).is_none());
+    }
+
+    #[tokio::test]
+    async fn delays_removals() {
+        time::pause();
+
+        let (tx, rx) = channel();
+        let debounce =
+            Debounce::new(rx, Duration::from_secs(1)).with_removal_delay(Duration::from_secs(10));
+        let mut debounce = task::spawn(debounce);
+
+        tx.send(Ok(Change::Insert(0, "a"))).unwrap();
+        tx.send(Ok(Change::Insert(1, "b"))).unwrap();
+        assert_pending!(debounce.poll_next());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut debounce), ["+0=a", "+1=b"]);
+
+        // Removed services are kept until the delay has passed.
+        tx.send(Ok(Change::Remove(0))).unwrap();
+        tx.send(Ok(Change::Remove(1))).unwrap();
+        assert_pending!(debounce.poll_next());
+        time::sleep(Duration::from_secs(1)).await;
+        assert!(changes(&mut debounce).is_empty());
+
+        // A draining service that is inserted again is not removed.
+        tx.send(Ok(Change::Insert(1, "c"))).unwrap();
+        assert_pending!(debounce.poll_next());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut debounce), ["+1=c"]);
+
+        time::sleep(Duration::from_secs(9)).await;
+        assert_eq!(changes(&mut debounce), ["-0"]);
+        time::sleep(Duration::from_secs(10)).await;
+        assert!(changes(&mut debounce).is_empty());
+    }
+}
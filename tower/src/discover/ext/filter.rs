@@ -0,0 +1,89 @@
+use crate::discover::{Change, Discover};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Discover returned by the [`filter`] combinator.
+    ///
+    /// [`filter`]: crate::discover::DiscoverExt::filter
+    pub struct Filter<D, F>
+    where
+        D: Discover,
+    {
+        #[pin]
+        discover: D,
+        predicate: F,
+        // The keys of services that have been passed on and not yet removed.
+        passed: HashSet<D::Key>,
+    }
+}
+
+impl<D, F> Filter<D, F>
+where
+    D: Discover,
+{
+    /// Creates a new [`Filter`] discover.
+    pub fn new(discover: D, predicate: F) -> Self {
+        Self {
+            discover,
+            predicate,
+            passed: HashSet::new(),
+        }
+    }
+}
+
+impl<D, F> Stream for Filter<D, F>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    F: FnMut(&D::Key, &D::Service) -> bool,
+{
+    type Item = Result<Change<D::Key, D::Service>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            let change = match ready!(this.discover.as_mut().poll_discover(cx)).transpose()? {
+                None => return Poll::Ready(None),
+                Some(Change::Insert(k, svc)) => {
+                    if (this.predicate)(&k, &svc) {
+                        this.passed.insert(k.clone());
+                        Change::Insert(k, svc)
+                    } else if this.passed.remove(&k) {
+                        // The service that replaces a passed service is rejected.
+                        Change::Remove(k)
+                    } else {
+                        continue;
+                    }
+                }
+                Some(Change::Remove(k)) => {
+                    if !this.passed.remove(&k) {
+                        continue;
+                    }
+                    Change::Remove(k)
+                }
+            };
+            return Poll::Ready(Some(Ok(change)));
+        }
+    }
+}
+
+impl<D, F> fmt::Debug for Filter<D, F>
+where
+    D: Discover + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("discover", &self.discover)
+            .field("predicate", &format_args!("{}", std::any::type_name::<F>()))
+            .field("passed", &self.passed.len())
+            .finish()
+    }
+}
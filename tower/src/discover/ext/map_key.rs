@@ -0,0 +1,56 @@
+use crate::discover::{Change, Discover};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Discover returned by the [`map_key`] combinator.
+    ///
+    /// [`map_key`]: crate::discover::DiscoverExt::map_key
+    pub struct MapKey<D, F> {
+        #[pin]
+        discover: D,
+        f: F,
+    }
+}
+
+impl<D, F> MapKey<D, F> {
+    /// Creates a new [`MapKey`] discover.
+    pub const fn new(discover: D, f: F) -> Self {
+        Self { discover, f }
+    }
+}
+
+impl<D, F, K> Stream for MapKey<D, F>
+where
+    D: Discover,
+    F: FnMut(D::Key) -> K,
+{
+    type Item = Result<Change<K, D::Service>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Insert(k, svc)) => Change::Insert((this.f)(k), svc),
+            Some(Change::Remove(k)) => Change::Remove((this.f)(k)),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+impl<D, F> fmt::Debug for MapKey<D, F>
+where
+    D: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapKey")
+            .field("discover", &self.discover)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
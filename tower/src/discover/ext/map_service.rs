@@ -0,0 +1,56 @@
+use crate::discover::{Change, Discover};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Discover returned by the [`map_service`] combinator.
+    ///
+    /// [`map_service`]: crate::discover::DiscoverExt::map_service
+    pub struct MapService<D, F> {
+        #[pin]
+        discover: D,
+        f: F,
+    }
+}
+
+impl<D, F> MapService<D, F> {
+    /// Creates a new [`MapService`] discover.
+    pub const fn new(discover: D, f: F) -> Self {
+        Self { discover, f }
+    }
+}
+
+impl<D, F, S> Stream for MapService<D, F>
+where
+    D: Discover,
+    F: FnMut(D::Service) -> S,
+{
+    type Item = Result<Change<D::Key, S>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Insert(k, svc)) => Change::Insert(k, (this.f)(svc)),
+            Some(Change::Remove(k)) => Change::Remove(k),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+impl<D, F> fmt::Debug for MapService<D, F>
+where
+    D: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MapService")
+            .field("discover", &self.discover)
+            .field("f", &format_args!("{}", std::any::type_name::<F>()))
+            .finish()
+    }
+}
@@ -0,0 +1,82 @@
+use crate::discover::{Change, Discover};
+use futures_core::Stream;
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Discover returned by the [`merge`] combinator.
+    ///
+    /// [`merge`]: crate::discover::DiscoverExt::merge
+    #[derive(Debug)]
+    pub struct Merge<A, B> {
+        #[pin]
+        a: A,
+        #[pin]
+        b: B,
+        a_done: bool,
+        b_done: bool,
+        // Whether `b` is polled first, so that neither source starves the other.
+        b_first: bool,
+    }
+}
+
+impl<A, B> Merge<A, B> {
+    /// Creates a new [`Merge`] discover.
+    pub const fn new(a: A, b: B) -> Self {
+        Self {
+            a,
+            b,
+            a_done: false,
+            b_done: false,
+            b_first: false,
+        }
+    }
+}
+
+impl<A, B> Stream for Merge<A, B>
+where
+    A: Discover,
+    B: Discover<Key = A::Key, Service = A::Service, Error = A::Error>,
+{
+    type Item = Result<Change<A::Key, A::Service>, A::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        *this.b_first = !*this.b_first;
+        for poll_b in [*this.b_first, !*this.b_first] {
+            let poll = if poll_b {
+                poll_unless_done(this.b.as_mut(), this.b_done, cx)
+            } else {
+                poll_unless_done(this.a.as_mut(), this.a_done, cx)
+            };
+            if let Poll::Ready(Some(change)) = poll {
+                return Poll::Ready(Some(change));
+            }
+        }
+
+        if *this.a_done && *this.b_done {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Polls `discover` for a change, unless it has ended.
+fn poll_unless_done<D: Discover>(
+    discover: Pin<&mut D>,
+    done: &mut bool,
+    cx: &mut Context<'_>,
+) -> Poll<Option<Result<Change<D::Key, D::Service>, D::Error>>> {
+    if *done {
+        return Poll::Ready(None);
+    }
+    let poll = discover.poll_discover(cx);
+    if let Poll::Ready(None) = poll {
+        *done = true;
+    }
+    poll
+}
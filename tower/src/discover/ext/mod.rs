@@ -0,0 +1,184 @@
+mod filter;
+mod map_key;
+mod map_service;
+mod merge;
+mod with_layer;
+
+pub use self::{
+    filter::Filter, map_key::MapKey, map_service::MapService, merge::Merge, with_layer::WithLayer,
+};
+
+use super::Discover;
+use std::hash::Hash;
+use tower_layer::Layer;
+
+/// An extension trait for [`Discover`]s that provides a variety of convenient adapters.
+///
+/// # Examples
+///
+/// ```rust
+/// use tower::discover::{DiscoverExt, ServiceList};
+/// use tower::layer::layer_fn;
+///
+/// # use std::task::{Context, Poll};
+/// # struct Backend;
+/// # impl tower::Service<()> for Backend {
+/// #     type Response = ();
+/// #     type Error = ();
+/// #     type Future = std::future::Ready<Result<(), ()>>;
+/// #     fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> { Poll::Ready(Ok(())) }
+/// #     fn call(&mut self, _: ()) -> Self::Future { std::future::ready(Ok(())) }
+/// # }
+/// # struct Instrumented<S>(S);
+/// let primary = ServiceList::new(vec![Backend, Backend]).map_key(|i| ("primary", i));
+/// let fallback = ServiceList::new(vec![Backend]).map_key(|i| ("fallback", i));
+///
+/// // Endpoints from both sources, each wrapped in a middleware.
+/// let discover = primary
+///     .merge(fallback)
+///     .with_layer(layer_fn(Instrumented));
+/// # let _ = discover;
+/// ```
+pub trait DiscoverExt: Discover {
+    /// Maps each discovered service to a new value.
+    fn map_service<F, S>(self, f: F) -> MapService<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Service) -> S,
+    {
+        MapService::new(self, f)
+    }
+
+    /// Wraps each discovered service with `layer`.
+    ///
+    /// This is how, for instance, every discovered service can be given a load metric, or a
+    /// timeout.
+    fn with_layer<L>(self, layer: L) -> WithLayer<Self, L>
+    where
+        Self: Sized,
+        L: Layer<Self::Service>,
+    {
+        WithLayer::new(self, layer)
+    }
+
+    /// Only passes on the discovered services for which `predicate` returns `true`.
+    ///
+    /// When a service that was passed on is replaced by one that `predicate` rejects, the
+    /// service is removed.
+    fn filter<F>(self, predicate: F) -> Filter<Self, F>
+    where
+        Self: Sized,
+        Self::Key: Hash + Clone,
+        F: FnMut(&Self::Key, &Self::Service) -> bool,
+    {
+        Filter::new(self, predicate)
+    }
+
+    /// Maps the key of each discovered service to a new key.
+    ///
+    /// `f` must map equal keys to equal keys, and distinct keys to distinct keys, so that
+    /// services are still identified correctly.
+    fn map_key<F, K>(self, f: F) -> MapKey<Self, F>
+    where
+        Self: Sized,
+        F: FnMut(Self::Key) -> K,
+    {
+        MapKey::new(self, f)
+    }
+
+    /// Combines the services discovered by `self` and `other`.
+    ///
+    /// Both sources must yield keys that are distinct from each other's. When they may not be,
+    /// namespace the keys of each source with [`map_key`](DiscoverExt::map_key) first. The
+    /// merged stream ends when both sources have ended.
+    fn merge<D>(self, other: D) -> Merge<Self, D>
+    where
+        Self: Sized,
+        D: Discover<Key = Self::Key, Service = Self::Service, Error = Self::Error>,
+    {
+        Merge::new(self, other)
+    }
+}
+
+impl<D: Discover + ?Sized> DiscoverExt for D {}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::stream;
+    use std::convert::Infallible;
+    use tokio_test::{assert_ready, task};
+
+    use super::*;
+    use crate::discover::Change;
+    use crate::layer::layer_fn;
+
+    type Changes = Vec<Result<Change<usize, &'static str>, Infallible>>;
+
+    fn collect<D>(discover: D) -> Vec<String>
+    where
+        D: Discover + Unpin,
+        D::Key: std::fmt::Debug,
+        D::Service: std::fmt::Debug,
+        D::Error: std::fmt::Debug,
+    {
+        let mut discover = task::spawn(discover);
+        let mut changes = Vec::new();
+        while let Some(change) =
+            assert_ready!(discover.enter(|cx, discover| discover.poll_discover(cx)))
+        {
+            changes.push(match change.unwrap() {
+                Change::Insert(k, svc) => format!("+{:?}={:?}", k, svc),
+                Change::Remove(k) => format!("-{:?}", k),
+            });
+        }
+        changes
+    }
+
+    #[test]
+    fn filter_drops_rejected_services() {
+        let changes: Changes = vec![
+            Ok(Change::Insert(0, "keep")),
+            Ok(Change::Insert(1, "drop")),
+            Ok(Change::Remove(1)),
+            Ok(Change::Insert(0, "drop")),
+            Ok(Change::Remove(0)),
+        ];
+        let discover = stream::iter(changes).filter(|_: &usize, svc: &&str| *svc == "keep");
+        assert_eq!(collect(discover), ["+0=\"keep\"", "-0"]);
+    }
+
+    #[test]
+    fn map_service_maps_inserted_services() {
+        let changes: Changes = vec![
+            Ok(Change::Insert(0, "zero")),
+            Ok(Change::Insert(0, "replaced")),
+            Ok(Change::Remove(0)),
+        ];
+        let discover = stream::iter(changes).map_service(str::len);
+        assert_eq!(collect(discover), ["+0=4", "+0=8", "-0"]);
+    }
+
+    #[test]
+    fn with_layer_wraps_inserted_services() {
+        let changes: Changes = vec![Ok(Change::Insert(0, "a")), Ok(Change::Remove(0))];
+        let discover =
+            stream::iter(changes).with_layer(layer_fn(|svc: &'static str| (svc, "wrapped")));
+        assert_eq!(collect(discover), ["+0=(\"a\", \"wrapped\")", "-0"]);
+    }
+
+    #[test]
+    fn merge_namespaced_keys() {
+        let a: Changes = vec![Ok(Change::Insert(0, "a")), Ok(Change::Remove(0))];
+        let b: Changes = vec![Ok(Change::Insert(0, "b"))];
+        let discover = stream::iter(a)
+            .map_key(|k| ("a", k))
+            .merge(stream::iter(b).map_key(|k| ("b", k)))
+            .map_service(str::to_uppercase);
+        let mut changes = collect(discover);
+        changes.sort();
+        assert_eq!(
+            changes,
+            ["+(\"a\", 0)=\"A\"", "+(\"b\", 0)=\"B\"", "-(\"a\", 0)"]
+        );
+    }
+}
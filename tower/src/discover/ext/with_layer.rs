@@ -0,0 +1,45 @@
+use crate::discover::{Change, Discover};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tower_layer::Layer;
+
+pin_project! {
+    /// Discover returned by the [`with_layer`] combinator.
+    ///
+    /// [`with_layer`]: crate::discover::DiscoverExt::with_layer
+    #[derive(Debug)]
+    pub struct WithLayer<D, L> {
+        #[pin]
+        discover: D,
+        layer: L,
+    }
+}
+
+impl<D, L> WithLayer<D, L> {
+    /// Creates a new [`WithLayer`] discover.
+    pub const fn new(discover: D, layer: L) -> Self {
+        Self { discover, layer }
+    }
+}
+
+impl<D, L> Stream for WithLayer<D, L>
+where
+    D: Discover,
+    L: Layer<D::Service>,
+{
+    type Item = Result<Change<D::Key, L::Service>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Insert(k, svc)) => Change::Insert(k, this.layer.layer(svc)),
+            Some(Change::Remove(k)) => Change::Remove(k),
+        };
+        Poll::Ready(Some(Ok(change)))
+    }
+}
@@ -0,0 +1,350 @@
+use super::{Change, Snapshots};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{
+    error::Error,
+    fmt, fs,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep_until, Instant, Sleep};
+use tower_service::Service;
+use tracing::{debug, trace};
+
+pin_project! {
+    /// Discovers services from a list of endpoints in a file, reloading it when it changes.
+    ///
+    /// Each non-empty line of the file that does not start with `#` describes an endpoint, either
+    /// as a target on its own, such as an address, or as `key=target`. A target on its own is
+    /// also used as the endpoint's key. Surrounding whitespace is ignored. For example:
+    ///
+    /// ```text
+    /// # Endpoints keyed by their address.
+    /// 10.0.0.1:8080
+    /// 10.0.0.2:8080
+    /// # An endpoint with a stable key.
+    /// primary = 10.0.0.3:8080
+    /// ```
+    ///
+    /// A service is built for the target of each endpoint with `make`, typically a
+    /// [`MakeService`]. See [`Snapshots`] for how changes to the file are turned into
+    /// [`Change`]s.
+    ///
+    /// The modification time and length of the file are checked periodically, and the file is
+    /// read again when either changes. The file is read synchronously, so it should be small and
+    /// on a local file system.
+    ///
+    /// When the file can't be read, or can't be parsed, the error is yielded, and the endpoints
+    /// from the last successful read remain in use. Errors reading the file are yielded every
+    /// time the file is checked, while parse errors are yielded once for every modification. To
+    /// keep a balancer running across such errors, configure it to tolerate discovery errors,
+    /// for instance with [`DiscoverPolicy`].
+    ///
+    /// [`MakeService`]: crate::MakeService
+    /// [`DiscoverPolicy`]: crate::balance::p2c::DiscoverPolicy
+    pub struct FileList<M>
+    where
+        M: Service<String>,
+    {
+        #[pin]
+        inner: Snapshots<Reads, M, String, String>,
+    }
+}
+
+/// An error parsing a file of endpoints.
+#[derive(Debug)]
+pub struct ParseError {
+    path: PathBuf,
+    line: usize,
+    reason: &'static str,
+}
+
+/// Reads the file periodically, yielding its endpoints when it has changed.
+#[derive(Debug)]
+struct Reads {
+    path: PathBuf,
+    // The delay until the next check, or `None` if the file should be checked now.
+    sleep: Option<Pin<Box<Sleep>>>,
+    interval: Duration,
+    // The modification time and length of the file when it was last read.
+    read: Option<(SystemTime, u64)>,
+    // An error to yield from `FileList`.
+    error: Option<crate::BoxError>,
+}
+
+impl<M> FileList<M>
+where
+    M: Service<String>,
+{
+    /// Discovers the endpoints listed in the file at `path`, building a service for each of them
+    /// with `make`.
+    ///
+    /// By default, the file is checked for changes every 5 seconds.
+    pub fn new<P: AsRef<Path>>(path: P, make: M) -> Self {
+        let reads = Reads {
+            path: path.as_ref().to_path_buf(),
+            sleep: None,
+            interval: Duration::from_secs(5),
+            read: None,
+            error: None,
+        };
+        Self {
+            inner: Snapshots::new(reads, make),
+        }
+    }
+
+    /// Sets the interval between checks for changes to the file.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.inner.snapshots_mut().interval = interval;
+        self
+    }
+}
+
+impl<M> Stream for FileList<M>
+where
+    M: Service<String>,
+    M::Error: Into<crate::BoxError>,
+{
+    type Item = Result<Change<String, M::Response>, crate::BoxError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut inner = self.project().inner;
+        if let Poll::Ready(change) = inner.as_mut().poll_next(cx) {
+            return Poll::Ready(change.map(|change| change.map_err(Into::into)));
+        }
+        match inner.snapshots_pin_mut().get_mut().error.take() {
+            Some(error) => Poll::Ready(Some(Err(error))),
+            None => Poll::Pending,
+        }
+    }
+}
+
+impl<M> fmt::Debug for FileList<M>
+where
+    M: Service<String> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("FileList")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// ===== impl Reads =====
+
+impl Reads {
+    /// Reads the file if it has changed since it was last read.
+    fn read_if_changed(&mut self) -> Result<Option<HashMap<String, String>>, crate::BoxError> {
+        let metadata = fs::metadata(&self.path)?;
+        let version = (metadata.modified()?, metadata.len());
+        if self.read == Some(version) {
+            return Ok(None);
+        }
+
+        trace!(path = ?self.path, "reading endpoints");
+        // Parse errors are only reported once for every modification.
+        self.read = Some(version);
+        let contents = fs::read_to_string(&self.path)?;
+        parse(&self.path, &contents).map(Some).map_err(Into::into)
+    }
+}
+
+impl Stream for Reads {
+    type Item = HashMap<String, String>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(sleep) = self.sleep.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+            }
+
+            let deadline = Instant::now() + self.interval;
+            match self.sleep.as_mut() {
+                Some(sleep) => sleep.as_mut().reset(deadline),
+                None => self.sleep = Some(Box::pin(sleep_until(deadline))),
+            }
+
+            match self.read_if_changed() {
+                Ok(Some(endpoints)) => return Poll::Ready(Some(endpoints)),
+                Ok(None) => {}
+                Err(error) => {
+                    debug!(path = ?self.path, %error, "failed to read endpoints");
+                    self.error = Some(error);
+                }
+            }
+        }
+    }
+}
+
+/// Parses the endpoints listed in `contents`.
+fn parse(path: &Path, contents: &str) -> Result<HashMap<String, String>, ParseError> {
+    let mut endpoints = HashMap::new();
+    for (i, line) in contents.lines().enumerate() {
+        let error = |reason| ParseError {
+            path: path.to_path_buf(),
+            line: i + 1,
+            reason,
+        };
+
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, target) = match line.find('=') {
+            Some(eq) => (line[..eq].trim(), line[eq + 1..].trim()),
+            None => (line, line),
+        };
+        if key.is_empty() {
+            return Err(error("missing key"));
+        }
+        if target.is_empty() {
+            return Err(error("missing target"));
+        }
+        if endpoints
+            .insert(key.to_string(), target.to_string())
+            .is_some()
+        {
+            return Err(error("duplicate key"));
+        }
+    }
+    Ok(endpoints)
+}
+
+// ===== impl ParseError =====
+
+impl ParseError {
+    /// Returns the path of the file that failed to parse.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the line number, starting from 1, at which the file failed to parse.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path.display(), self.line, self.reason)
+    }
+}
+
+impl Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::time;
+    use tokio_test::{assert_pending, task};
+    use tower_test::mock;
+
+    use super::*;
+
+    /// A file that is removed when it is dropped.
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new() -> Self {
+            static NEXT: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "tower-file-list-{}-{}",
+                std::process::id(),
+                NEXT.fetch_add(1, Ordering::Relaxed)
+            );
+            TempFile(std::env::temp_dir().join(name))
+        }
+
+        fn write(&self, contents: &str) {
+            fs::write(&self.0, contents).unwrap();
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    /// Polls `list` until it builds no more services, returning its changes in order.
+    fn changes(
+        list: &mut task::Spawn<FileList<mock::Mock<String, ()>>>,
+        handle: &mut mock::Handle<String, ()>,
+    ) -> Vec<String> {
+        let mut changes = Vec::new();
+        loop {
+            match list.poll_next() {
+                Poll::Ready(Some(Ok(Change::Insert(key, ())))) => changes.push(format!("+{}", key)),
+                Poll::Ready(Some(Ok(Change::Remove(key)))) => changes.push(format!("-{}", key)),
+                Poll::Ready(Some(Err(error))) => changes.push(format!("error: {}", error)),
+                Poll::Ready(None) => panic!("file list ended"),
+                Poll::Pending => match handle.poll_request() {
+                    Poll::Ready(Some((_, rsp))) => rsp.send_response(()),
+                    _ => break,
+                },
+            }
+        }
+        changes.sort();
+        changes
+    }
+
+    #[tokio::test]
+    async fn reloads_on_change() {
+        time::pause();
+
+        let file = TempFile::new();
+        file.write("# comment\n10.0.0.1:80\n\nprimary = 10.0.0.2:80\n");
+        let (make, mut handle) = mock::pair();
+        let list = FileList::new(&file.0, make).with_interval(Duration::from_secs(1));
+        let mut list = task::spawn(list);
+        assert_eq!(
+            changes(&mut list, &mut handle),
+            ["+10.0.0.1:80", "+primary"]
+        );
+
+        file.write("primary = 10.0.0.3:80\n");
+        assert_pending!(list.poll_next());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            changes(&mut list, &mut handle),
+            ["+primary", "-10.0.0.1:80"]
+        );
+
+        time::sleep(Duration::from_secs(1)).await;
+        assert!(changes(&mut list, &mut handle).is_empty());
+    }
+
+    #[tokio::test]
+    async fn keeps_endpoints_on_parse_error() {
+        time::pause();
+
+        let file = TempFile::new();
+        file.write("10.0.0.1:80\n");
+        let (make, mut handle) = mock::pair();
+        let list = FileList::new(&file.0, make).with_interval(Duration::from_secs(1));
+        let mut list = task::spawn(list);
+        assert_eq!(changes(&mut list, &mut handle), ["+10.0.0.1:80"]);
+
+        file.write("10.0.0.1:80\n = 10.0.0.2:80\n");
+        time::sleep(Duration::from_secs(1)).await;
+        let error = format!("error: {}:2: missing key", file.0.display());
+        assert_eq!(changes(&mut list, &mut handle), [error]);
+
+        // The error is only reported once.
+        time::sleep(Duration::from_secs(1)).await;
+        assert!(changes(&mut list, &mut handle).is_empty());
+
+        file.write("10.0.0.2:80\n");
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(
+            changes(&mut list, &mut handle),
+            ["+10.0.0.2:80", "-10.0.0.1:80"]
+        );
+    }
+}
@@ -0,0 +1,507 @@
+use super::{Change, Discover};
+use crate::util::rng::{HasherRng, Rng};
+use crate::BoxError;
+use futures_core::{ready, Stream};
+use futures_util::stream::FuturesUnordered;
+use pin_project_lite::pin_project;
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep, Sleep};
+use tracing::{debug, trace};
+
+/// Checks the health of a service.
+///
+/// This is implemented for closures that take a service and return a future that resolves to a
+/// [`Result`], such as `|svc| svc.oneshot(request)`.
+pub trait Probe<S> {
+    /// The type of responses to a successful probe.
+    type Response;
+
+    /// The future returned by [`probe`].
+    ///
+    /// [`probe`]: crate::discover::Probe::probe
+    type Future: Future<Output = Result<Self::Response, BoxError>>;
+
+    /// Probes `service`, resolving to [`Ok`] if it is healthy.
+    fn probe(&mut self, service: S) -> Self::Future;
+}
+
+impl<F, S, U, R, E> Probe<S> for F
+where
+    F: FnMut(S) -> U,
+    U: Future<Output = Result<R, E>>,
+    E: Into<BoxError>,
+{
+    type Response = R;
+    type Future = futures_util::future::ErrInto<U, BoxError>;
+
+    fn probe(&mut self, service: S) -> Self::Future {
+        use futures_util::TryFutureExt;
+        self(service).err_into()
+    }
+}
+
+pin_project! {
+    /// Actively checks the health of discovered services, withholding unhealthy ones.
+    ///
+    /// Every service that `discover` yields is probed periodically with `probe`, which is given a
+    /// clone of the service, typically to send it a request that only a healthy service responds
+    /// to successfully. Probe intervals are shortened by a random amount, up to the configured
+    /// jitter, so that the services aren't all probed at once.
+    ///
+    /// Services are considered healthy when they are discovered. After a configured number of
+    /// consecutive failed probes, a service is considered unhealthy, and a [`Change::Remove`] is
+    /// yielded for it, so that a balancer stops sending requests to it. Once it passes a
+    /// configured number of consecutive probes, it is considered healthy again, and is yielded
+    /// again with a [`Change::Insert`].
+    ///
+    /// Probes are not subject to a timeout, and a service is not probed again until its current
+    /// probe completes. To guard against probes that never complete, apply a timeout within
+    /// `probe`, for instance with [`tokio::time::timeout`].
+    pub struct HealthCheck<D, P>
+    where
+        D: Discover,
+        P: Probe<D::Service>,
+    {
+        #[pin]
+        discover: D,
+        checks: Checks<D::Key, D::Service, P>,
+    }
+}
+
+/// The health of the discovered services.
+struct Checks<K, S, P>
+where
+    P: Probe<S>,
+{
+    probe: P,
+    endpoints: HashMap<K, Endpoint<S>>,
+    next_generation: u64,
+    // The delays until each service is probed next.
+    waiting: FuturesUnordered<Waiting<K>>,
+    probing: FuturesUnordered<Probing<K, P::Future>>,
+    interval: Duration,
+    jitter: f64,
+    healthy_threshold: usize,
+    unhealthy_threshold: usize,
+    rng: HasherRng,
+    ready: VecDeque<Change<K, S>>,
+}
+
+/// A discovered service.
+struct Endpoint<S> {
+    service: S,
+    healthy: bool,
+    // The number of consecutive probes that have succeeded or failed since the health of the
+    // service last changed.
+    successes: usize,
+    failures: usize,
+    // Distinguishes the service from earlier ones with the same key, so that their probes are
+    // ignored.
+    generation: u64,
+}
+
+pin_project! {
+    /// The delay until a service is probed.
+    struct Waiting<K> {
+        #[pin]
+        sleep: Sleep,
+        key: Option<K>,
+        generation: u64,
+    }
+}
+
+pin_project! {
+    /// A probe of a service.
+    struct Probing<K, F> {
+        #[pin]
+        future: F,
+        key: Option<K>,
+        generation: u64,
+    }
+}
+
+impl<D, P> HealthCheck<D, P>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Clone,
+    P: Probe<D::Service>,
+{
+    /// Checks the health of the services discovered by `discover` with `probe`.
+    ///
+    /// By default, services are probed every 10 seconds with a jitter of 10%, are considered
+    /// unhealthy after 3 consecutive failed probes, and are considered healthy again after 2
+    /// consecutive successful probes.
+    pub fn new(discover: D, probe: P) -> Self {
+        Self {
+            discover,
+            checks: Checks {
+                probe,
+                endpoints: HashMap::new(),
+                next_generation: 0,
+                waiting: FuturesUnordered::new(),
+                probing: FuturesUnordered::new(),
+                interval: Duration::from_secs(10),
+                jitter: 0.1,
+                healthy_threshold: 2,
+                unhealthy_threshold: 3,
+                rng: HasherRng::new(),
+                ready: VecDeque::new(),
+            },
+        }
+    }
+
+    /// Sets the interval between probes of each service.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.checks.interval = interval;
+        self
+    }
+
+    /// Sets the largest fraction by which a probe interval is randomly shortened.
+    ///
+    /// # Panics
+    ///
+    /// If `jitter` is not between 0.0 and 1.0.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "jitter must be between 0.0 and 1.0"
+        );
+        self.checks.jitter = jitter;
+        self
+    }
+
+    /// Sets the number of consecutive successful probes after which an unhealthy service is
+    /// considered healthy.
+    ///
+    /// # Panics
+    ///
+    /// If `threshold` is zero.
+    pub fn with_healthy_threshold(mut self, threshold: usize) -> Self {
+        assert!(threshold > 0, "healthy threshold must be positive");
+        self.checks.healthy_threshold = threshold;
+        self
+    }
+
+    /// Sets the number of consecutive failed probes after which a healthy service is considered
+    /// unhealthy.
+    ///
+    /// # Panics
+    ///
+    /// If `threshold` is zero.
+    pub fn with_unhealthy_threshold(mut self, threshold: usize) -> Self {
+        assert!(threshold > 0, "unhealthy threshold must be positive");
+        self.checks.unhealthy_threshold = threshold;
+        self
+    }
+
+    /// Returns whether the service with the given key is currently considered healthy, or `None`
+    /// if no such service has been discovered.
+    pub fn is_healthy(&self, key: &D::Key) -> Option<bool> {
+        self.checks.endpoints.get(key).map(|e| e.healthy)
+    }
+}
+
+impl<D, P> Stream for HealthCheck<D, P>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Clone,
+    P: Probe<D::Service>,
+{
+    type Item = Result<Change<D::Key, D::Service>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        let checks = this.checks;
+        loop {
+            if let Some(change) = checks.ready.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            match this.discover.as_mut().poll_discover(cx) {
+                Poll::Ready(Some(Ok(change))) => {
+                    checks.discovered(change);
+                    continue;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => return Poll::Ready(None),
+                Poll::Pending => {}
+            }
+
+            while let Poll::Ready(Some((key, generation))) =
+                Pin::new(&mut checks.waiting).poll_next(cx)
+            {
+                checks.start_probe(key, generation);
+            }
+
+            match ready!(Pin::new(&mut checks.probing).poll_next(cx)) {
+                Some((key, generation, result)) => checks.probed(key, generation, result),
+                None => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<D, P> fmt::Debug for HealthCheck<D, P>
+where
+    D: Discover + fmt::Debug,
+    P: Probe<D::Service> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HealthCheck")
+            .field("discover", &self.discover)
+            .field("probe", &self.checks.probe)
+            .field("endpoints", &self.checks.endpoints.len())
+            .field("interval", &self.checks.interval)
+            .field("jitter", &self.checks.jitter)
+            .field("healthy_threshold", &self.checks.healthy_threshold)
+            .field("unhealthy_threshold", &self.checks.unhealthy_threshold)
+            .finish()
+    }
+}
+
+// ===== impl Checks =====
+
+impl<K, S, P> Checks<K, S, P>
+where
+    K: Hash + Eq + Clone,
+    S: Clone,
+    P: Probe<S>,
+{
+    /// Tracks the services that are inserted and removed, passing on changes to healthy ones.
+    fn discovered(&mut self, change: Change<K, S>) {
+        match change {
+            Change::Insert(key, service) => {
+                self.next_generation += 1;
+                let endpoint = Endpoint {
+                    service: service.clone(),
+                    healthy: true,
+                    successes: 0,
+                    failures: 0,
+                    generation: self.next_generation,
+                };
+                self.endpoints.insert(key.clone(), endpoint);
+                self.schedule_probe(key.clone(), self.next_generation);
+                self.ready.push_back(Change::Insert(key, service));
+            }
+            Change::Remove(key) => match self.endpoints.remove(&key) {
+                Some(endpoint) if !endpoint.healthy => {
+                    trace!("unhealthy service removed");
+                }
+                _ => self.ready.push_back(Change::Remove(key)),
+            },
+        }
+    }
+
+    /// Probes the service with the given key again after a jittered interval.
+    fn schedule_probe(&mut self, key: K, generation: u64) {
+        let jitter = 1.0 - self.jitter * self.rng.next_f64();
+        self.waiting.push(Waiting {
+            sleep: sleep(self.interval.mul_f64(jitter)),
+            key: Some(key),
+            generation,
+        });
+    }
+
+    /// Probes the service with the given key, unless it has been removed or replaced.
+    fn start_probe(&mut self, key: K, generation: u64) {
+        let endpoint = match self.endpoints.get(&key) {
+            Some(endpoint) if endpoint.generation == generation => endpoint,
+            _ => return,
+        };
+        trace!("probing service");
+        self.probing.push(Probing {
+            future: self.probe.probe(endpoint.service.clone()),
+            key: Some(key),
+            generation,
+        });
+    }
+
+    /// Records the result of a probe, passing on the service if its health changed.
+    fn probed(&mut self, key: K, generation: u64, result: Result<P::Response, BoxError>) {
+        let endpoint = match self.endpoints.get_mut(&key) {
+            Some(endpoint) if endpoint.generation == generation => endpoint,
+            _ => {
+                trace!("discarding outdated probe");
+                return;
+            }
+        };
+
+        match result {
+            Ok(_) => {
+                endpoint.failures = 0;
+                endpoint.successes += 1;
+                if !endpoint.healthy && endpoint.successes >= self.healthy_threshold {
+                    debug!("service is healthy");
+                    endpoint.healthy = true;
+                    self.ready
+                        .push_back(Change::Insert(key.clone(), endpoint.service.clone()));
+                }
+            }
+            Err(error) => {
+                trace!(%error, "probe failed");
+                endpoint.successes = 0;
+                endpoint.failures += 1;
+                if endpoint.healthy && endpoint.failures >= self.unhealthy_threshold {
+                    debug!(%error, "service is unhealthy");
+                    endpoint.healthy = false;
+                    self.ready.push_back(Change::Remove(key.clone()));
+                }
+            }
+        }
+        self.schedule_probe(key, generation);
+    }
+}
+
+// ===== impl Waiting =====
+
+impl<K> Future for Waiting<K> {
+    type Output = (K, u64);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        ready!(this.sleep.poll(cx));
+        let key = this.key.take().expect("polled after completion");
+        Poll::Ready((key, *this.generation))
+    }
+}
+
+// ===== impl Probing =====
+
+impl<K, F, R> Future for Probing<K, F>
+where
+    F: Future<Output = Result<R, BoxError>>,
+{
+    type Output = (K, u64, Result<R, BoxError>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.future.poll(cx));
+        let key = this.key.take().expect("polled after completion");
+        Poll::Ready((key, *this.generation, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{future, stream, StreamExt};
+    use std::collections::HashSet;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+    use tokio::time;
+    use tokio_test::task;
+
+    use super::*;
+
+    type Failing = Arc<Mutex<HashSet<&'static str>>>;
+
+    /// Returns a probe that fails for the services in the returned set.
+    fn probe() -> (
+        Failing,
+        impl FnMut(&'static str) -> future::Ready<Result<(), &'static str>>,
+    ) {
+        let failing = Failing::default();
+        let probe = {
+            let failing = failing.clone();
+            move |svc| {
+                future::ready(if failing.lock().unwrap().contains(svc) {
+                    Err("unhealthy")
+                } else {
+                    Ok(())
+                })
+            }
+        };
+        (failing, probe)
+    }
+
+    /// Discovers the given changes, and then never ends.
+    fn discover(
+        changes: Vec<Change<usize, &'static str>>,
+    ) -> impl Discover<Key = usize, Service = &'static str, Error = Infallible> + Unpin {
+        stream::iter(changes.into_iter().map(Ok)).chain(stream::pending())
+    }
+
+    fn changes<S: Stream<Item = Result<Change<usize, &'static str>, Infallible>>>(
+        checks: &mut task::Spawn<S>,
+    ) -> Vec<String> {
+        let mut changes = Vec::new();
+        while let Poll::Ready(Some(change)) = checks.poll_next() {
+            changes.push(match change.unwrap() {
+                Change::Insert(key, service) => format!("+{}={}", key, service),
+                Change::Remove(key) => format!("-{}", key),
+            });
+        }
+        changes
+    }
+
+    #[tokio::test]
+    async fn withholds_unhealthy_services() {
+        time::pause();
+
+        let (failing, probe) = probe();
+        let discover = discover(vec![Change::Insert(0, "a"), Change::Insert(1, "b")]);
+        let checks = HealthCheck::new(discover, probe)
+            .with_interval(Duration::from_secs(1))
+            .with_jitter(0.0)
+            .with_unhealthy_threshold(2)
+            .with_healthy_threshold(2);
+        let mut checks = task::spawn(checks);
+        assert_eq!(changes(&mut checks), ["+0=a", "+1=b"]);
+
+        failing.lock().unwrap().insert("b");
+        time::sleep(Duration::from_secs(1)).await;
+        assert!(changes(&mut checks).is_empty());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut checks), ["-1"]);
+        assert_eq!(checks.is_healthy(&1), Some(false));
+
+        failing.lock().unwrap().clear();
+        time::sleep(Duration::from_secs(1)).await;
+        assert!(changes(&mut checks).is_empty());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut checks), ["+1=b"]);
+        assert_eq!(checks.is_healthy(&1), Some(true));
+    }
+
+    #[tokio::test]
+    async fn tracks_changes_to_unhealthy_services() {
+        time::pause();
+
+        let (failing, probe) = probe();
+        failing.lock().unwrap().insert("a");
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let discover = stream::poll_fn(move |cx| rx.poll_recv(cx)).map(Ok::<_, Infallible>);
+        let checks = HealthCheck::new(discover, probe)
+            .with_interval(Duration::from_secs(1))
+            .with_jitter(0.0)
+            .with_unhealthy_threshold(1)
+            .with_healthy_threshold(1);
+        let mut checks = task::spawn(checks);
+
+        tx.send(Change::Insert(0, "a")).unwrap();
+        assert_eq!(changes(&mut checks), ["+0=a"]);
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut checks), ["-0"]);
+
+        failing.lock().unwrap().clear();
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut checks), ["+0=a"]);
+
+        // A service that is removed while unhealthy is only removed once.
+        failing.lock().unwrap().insert("a");
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut checks), ["-0"]);
+        tx.send(Change::Remove(0)).unwrap();
+        assert!(changes(&mut checks).is_empty());
+        assert_eq!(checks.is_healthy(&0), None);
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+/// Describes a discovered service, apart from the service itself.
+///
+/// Metadata is published for the key of a service through a [`MetadataSender`], alongside the
+/// [`Change`]s of a [`Discover`], so that it can change without the service being torn down.
+/// Components that don't use a given piece of metadata ignore it:
+///
+/// - [`p2c::Balance`] prefers services that are not draining when it compares two services, and
+///   makes the metadata of each service available through [`Balance::metadata`].
+/// - The weight and zone are not interpreted by this crate, and are available for layers and
+///   balancers that are built on top of it.
+///
+/// [`Change`]: crate::discover::Change
+/// [`Discover`]: crate::discover::Discover
+/// [`p2c::Balance`]: crate::balance::p2c::Balance
+/// [`Balance::metadata`]: crate::balance::p2c::Balance::metadata
+#[derive(Clone, Debug, PartialEq)]
+pub struct Metadata {
+    weight: f64,
+    zone: Option<String>,
+    draining: bool,
+}
+
+/// Creates a channel that carries the [`Metadata`] of discovered services, alongside a
+/// [`Discover`].
+///
+/// Metadata is sent for the key of a service, and applies to the service with that key until it
+/// is cleared. It is kept when the service is removed, so that it applies again if the service is
+/// inserted again, as when an unhealthy service is withheld for a while. Since it
+/// doesn't go through the [`Discover`], it isn't affected by adapters that change the keys of
+/// services, and must be sent for the keys that the receiving balancer sees.
+///
+/// Metadata that is sent after the receiver is dropped is discarded.
+///
+/// [`Discover`]: crate::discover::Discover
+pub fn metadata_channel<K>() -> (MetadataSender<K>, MetadataReceiver<K>) {
+    let updates = Arc::new(Mutex::new(VecDeque::new()));
+    let tx = MetadataSender {
+        updates: Arc::downgrade(&updates),
+    };
+    (tx, MetadataReceiver { updates })
+}
+
+/// Sends the [`Metadata`] of discovered services.
+///
+/// Created with [`metadata_channel`].
+pub struct MetadataSender<K> {
+    updates: Weak<Mutex<VecDeque<(K, Option<Metadata>)>>>,
+}
+
+/// Receives the [`Metadata`] of discovered services.
+///
+/// Created with [`metadata_channel`].
+pub struct MetadataReceiver<K> {
+    updates: Arc<Mutex<VecDeque<(K, Option<Metadata>)>>>,
+}
+
+// ===== impl Metadata =====
+
+impl Metadata {
+    /// Returns metadata with a weight of 1.0, no zone, and that is not draining.
+    pub fn new() -> Self {
+        Self {
+            weight: 1.0,
+            zone: None,
+            draining: false,
+        }
+    }
+
+    /// Sets the relative weight of the service.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is negative or not finite.
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        assert!(
+            weight.is_finite() && weight >= 0.0,
+            "weight must be finite and non-negative"
+        );
+        self.weight = weight;
+        self
+    }
+
+    /// Sets the zone, such as an availability zone or region, that the service is in.
+    pub fn with_zone(mut self, zone: impl Into<String>) -> Self {
+        self.zone = Some(zone.into());
+        self
+    }
+
+    /// Sets whether the service is draining, and should not be sent new requests if others
+    /// are available.
+    pub fn with_draining(mut self, draining: bool) -> Self {
+        self.draining = draining;
+        self
+    }
+
+    /// Returns the relative weight of the service.
+    pub fn weight(&self) -> f64 {
+        self.weight
+    }
+
+    /// Returns the zone that the service is in, if it is known.
+    pub fn zone(&self) -> Option<&str> {
+        self.zone.as_deref()
+    }
+
+    /// Returns whether the service is draining.
+    pub fn is_draining(&self) -> bool {
+        self.draining
+    }
+}
+
+impl Default for Metadata {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ===== impl MetadataSender =====
+
+impl<K> MetadataSender<K> {
+    /// Sets the metadata of the service with the given key.
+    pub fn send(&self, key: K, metadata: Metadata) {
+        self.push(key, Some(metadata));
+    }
+
+    /// Clears the metadata of the service with the given key.
+    pub fn clear(&self, key: K) {
+        self.push(key, None);
+    }
+
+    fn push(&self, key: K, metadata: Option<Metadata>) {
+        if let Some(updates) = self.updates.upgrade() {
+            updates
+                .lock()
+                .expect("metadata lock")
+                .push_back((key, metadata));
+        }
+    }
+}
+
+impl<K> Clone for MetadataSender<K> {
+    fn clone(&self) -> Self {
+        Self {
+            updates: self.updates.clone(),
+        }
+    }
+}
+
+impl<K> fmt::Debug for MetadataSender<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataSender").finish()
+    }
+}
+
+// ===== impl MetadataReceiver =====
+
+impl<K> MetadataReceiver<K> {
+    /// Returns the next metadata that was sent, in the order it was sent, or `None` if there is
+    /// none.
+    ///
+    /// Metadata that was cleared is received as `None` along with its key.
+    pub fn try_recv(&mut self) -> Option<(K, Option<Metadata>)> {
+        self.updates.lock().expect("metadata lock").pop_front()
+    }
+}
+
+impl<K> fmt::Debug for MetadataReceiver<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MetadataReceiver").finish()
+    }
+}
@@ -9,6 +9,9 @@
 //! services. If that service later goes away, a [`Change::Remove`] is yielded with that service's
 //! identifier. From that point forward, the identifier may be re-used.
 //!
+//! A service's [`Metadata`], such as whether it is draining, can be changed without replacing the
+//! service by sending it through a [`metadata_channel`], alongside the [`Discover`].
+//!
 //! # Examples
 //!
 //! ```rust
@@ -31,11 +34,69 @@
 //! }
 //! ```
 //!
+//! # Combinators
+//!
+//! [`DiscoverExt`] provides adapters that transform the services or keys that a [`Discover`]
+//! yields, filter them, or merge several [`Discover`]s into one.
+//!
+//! # Snapshots
+//!
+//! Service registries that deliver the complete set of endpoints whenever it changes can be
+//! adapted into a [`Discover`] with [`Snapshots`], which diffs consecutive endpoint sets and builds
+//! services for new endpoints. Names that resolve to a set of addresses, such as DNS names, can be
+//! discovered with [`Resolve`], which resolves them periodically, and endpoints listed in a local
+//! file can be discovered with [`FileList`], which reloads the file when it changes.
+//!
+//! These require the `discover-snapshots` feature.
+//!
+//! # Debouncing
+//!
+//! Registries that flap can yield bursts of changes that make a balancer insert and evict the
+//! same services repeatedly. [`Debounce`] coalesces the changes over a window, and can delay
+//! removals so that requests in flight to removed services can complete. It requires the
+//! `discover-debounce` feature.
+//!
+//! # Health checking
+//!
+//! [`HealthCheck`] probes discovered services periodically, and withholds them from the balancer
+//! while they are unhealthy. It requires the `discover-health` feature.
+//!
+//! # Subsetting
+//!
+//! When a client has access to a very large number of services, [`Subset`] can be used to limit
+//! it to a stable subset of them. See its documentation for details.
+//!
 //! [`TryStream`]: https://docs.rs/futures/latest/futures/stream/trait.TryStream.html
 
+#[cfg(feature = "discover-debounce")]
+mod debounce;
+mod ext;
+#[cfg(feature = "discover-snapshots")]
+mod file;
+#[cfg(feature = "discover-health")]
+mod health;
 mod list;
+mod metadata;
+#[cfg(feature = "discover-snapshots")]
+mod resolve;
+#[cfg(feature = "discover-snapshots")]
+mod snapshots;
+mod subset;
 
+#[cfg(feature = "discover-debounce")]
+pub use self::debounce::Debounce;
+pub use self::ext::{DiscoverExt, Filter, MapKey, MapService, Merge, WithLayer};
+#[cfg(feature = "discover-snapshots")]
+pub use self::file::{FileList, ParseError};
+#[cfg(feature = "discover-health")]
+pub use self::health::{HealthCheck, Probe};
 pub use self::list::ServiceList;
+pub use self::metadata::{metadata_channel, Metadata, MetadataReceiver, MetadataSender};
+#[cfg(feature = "discover-snapshots")]
+pub use self::resolve::Resolve;
+#[cfg(feature = "discover-snapshots")]
+pub use self::snapshots::{Snapshots, WatchSnapshots};
+pub use self::subset::Subset;
 
 use crate::sealed::Sealed;
 use futures_core::TryStream;
@@ -0,0 +1,433 @@
+use super::{Change, Snapshots};
+use crate::util::rng::{HasherRng, Rng};
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::{sleep_until, Instant, Sleep};
+use tower_service::Service;
+use tracing::{trace, warn};
+
+pin_project! {
+    /// Discovers services by periodically resolving a name to a set of addresses.
+    ///
+    /// [`Resolve`] calls `resolver`, a [`Service`] that resolves a `name` to the addresses it
+    /// currently refers to, much like a DNS lookup. Each address is used as the key of a service,
+    /// which is built for it with `make`, typically a [`MakeService`] or [`MakeConnection`]. When
+    /// an address is no longer returned by the resolver, its service is removed. See
+    /// [`Snapshots`] for how changes in the set of addresses are turned into [`Change`]s.
+    ///
+    /// The name is resolved immediately, and then again after every refresh interval. Refresh
+    /// intervals are shortened by a random amount, up to the configured jitter, so that many
+    /// clients that start at the same time don't all query the resolver at once.
+    ///
+    /// When resolution fails, the error is logged, the addresses from the last successful
+    /// resolution remain in use, and the name is resolved again after the next refresh interval.
+    /// If no resolution has succeeded for longer than the TTL, all addresses are removed until a
+    /// resolution succeeds.
+    ///
+    /// When `resolver` itself fails, by returning an error from [`Service::poll_ready`], it can't
+    /// be used again, so all addresses are removed and no more changes are yielded.
+    ///
+    /// [`MakeService`]: crate::MakeService
+    /// [`MakeConnection`]: crate::make::MakeConnection
+    pub struct Resolve<R, N, M>
+    where
+        R: Service<N>,
+        M: Service<SocketAddr>,
+    {
+        #[pin]
+        inner: Snapshots<Resolutions<R, N>, M, SocketAddr, SocketAddr>,
+    }
+}
+
+pin_project! {
+    /// Resolves a name periodically, yielding the resolved addresses.
+    struct Resolutions<R, N>
+    where
+        R: Service<N>,
+    {
+        resolver: R,
+        name: N,
+        #[pin]
+        resolving: Option<R::Future>,
+        // The delay until the next resolution, or `None` if the name should be resolved now.
+        sleep: Option<Pin<Box<Sleep>>>,
+        // When the last successful resolution completed, if its addresses are still in use.
+        resolved_at: Option<Instant>,
+        // Whether the resolver has failed, after which it is not used again.
+        resolver_failed: bool,
+        interval: Duration,
+        jitter: f64,
+        ttl: Duration,
+        rng: HasherRng,
+    }
+}
+
+impl<R, N, M> Resolve<R, N, M>
+where
+    R: Service<N, Response = Vec<SocketAddr>>,
+    R::Error: Into<crate::BoxError>,
+    N: Clone,
+    M: Service<SocketAddr>,
+{
+    /// Resolves `name` with `resolver` periodically, building a service for every address with
+    /// `make`.
+    ///
+    /// By default, the name is resolved every 30 seconds with a jitter of 10%, and addresses are
+    /// removed when no resolution has succeeded for 5 minutes.
+    pub fn new(resolver: R, name: N, make: M) -> Self {
+        let resolutions = Resolutions {
+            resolver,
+            name,
+            resolving: None,
+            sleep: None,
+            resolved_at: None,
+            resolver_failed: false,
+            interval: Duration::from_secs(30),
+            jitter: 0.1,
+            ttl: Duration::from_secs(5 * 60),
+            rng: HasherRng::new(),
+        };
+        Self {
+            inner: Snapshots::new(resolutions, make),
+        }
+    }
+
+    /// Sets the interval between resolutions.
+    pub fn with_interval(mut self, interval: Duration) -> Self {
+        self.inner.snapshots_mut().interval = interval;
+        self
+    }
+
+    /// Sets the fraction of the interval by which each interval is randomly shortened.
+    ///
+    /// # Panics
+    ///
+    /// If `jitter` is not between 0.0 and 1.0.
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "jitter must be between 0.0 and 1.0"
+        );
+        self.inner.snapshots_mut().jitter = jitter;
+        self
+    }
+
+    /// Sets how long the addresses of the last successful resolution remain in use while
+    /// resolution fails.
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.inner.snapshots_mut().ttl = ttl;
+        self
+    }
+}
+
+impl<R, N, M> Stream for Resolve<R, N, M>
+where
+    R: Service<N, Response = Vec<SocketAddr>>,
+    R::Error: Into<crate::BoxError>,
+    N: Clone,
+    M: Service<SocketAddr>,
+{
+    type Item = Result<Change<SocketAddr, M::Response>, M::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.project().inner.poll_next(cx)
+    }
+}
+
+impl<R, N, M> fmt::Debug for Resolve<R, N, M>
+where
+    R: Service<N> + fmt::Debug,
+    N: fmt::Debug,
+    M: Service<SocketAddr> + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolve")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+// ===== impl Resolutions =====
+
+impl<R, N> Resolutions<R, N>
+where
+    R: Service<N>,
+{
+    /// Schedules the next resolution after a jittered interval.
+    fn schedule(self: Pin<&mut Self>) {
+        let this = self.project();
+        let jitter = 1.0 - *this.jitter * this.rng.next_f64();
+        let delay = this.interval.mul_f64(jitter);
+        trace!(?delay, "scheduling resolution");
+        let deadline = Instant::now() + delay;
+        match this.sleep {
+            Some(sleep) => sleep.as_mut().reset(deadline),
+            None => *this.sleep = Some(Box::pin(sleep_until(deadline))),
+        }
+    }
+
+    /// Handles a failed resolution, returning an empty set of addresses if the last successful
+    /// resolution has expired.
+    fn failed(
+        mut self: Pin<&mut Self>,
+        error: crate::BoxError,
+    ) -> Option<HashMap<SocketAddr, SocketAddr>> {
+        warn!(%error, "resolution failed");
+        self.as_mut().schedule();
+        let this = self.project();
+        match *this.resolved_at {
+            Some(resolved_at) if resolved_at.elapsed() >= *this.ttl => {
+                warn!("resolved addresses expired");
+                *this.resolved_at = None;
+                Some(HashMap::new())
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<R, N> Stream for Resolutions<R, N>
+where
+    R: Service<N, Response = Vec<SocketAddr>>,
+    R::Error: Into<crate::BoxError>,
+    N: Clone,
+{
+    type Item = HashMap<SocketAddr, SocketAddr>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            let mut this = self.as_mut().project();
+            if *this.resolver_failed {
+                return Poll::Ready(None);
+            }
+
+            if let Some(resolving) = this.resolving.as_mut().as_pin_mut() {
+                let result = ready!(resolving.poll(cx));
+                this.resolving.set(None);
+                match result {
+                    Ok(addrs) => {
+                        trace!(addrs = addrs.len(), "resolved");
+                        *this.resolved_at = Some(Instant::now());
+                        self.as_mut().schedule();
+                        return Poll::Ready(Some(addrs.into_iter().map(|a| (a, a)).collect()));
+                    }
+                    Err(e) => {
+                        if let Some(expired) = self.as_mut().failed(e.into()) {
+                            return Poll::Ready(Some(expired));
+                        }
+                        continue;
+                    }
+                }
+            }
+
+            if let Some(sleep) = this.sleep.as_mut() {
+                ready!(sleep.as_mut().poll(cx));
+            }
+            if let Err(e) = ready!(this.resolver.poll_ready(cx)) {
+                let error: crate::BoxError = e.into();
+                warn!(%error, "resolver failed");
+                *this.resolver_failed = true;
+                *this.resolved_at = None;
+                return Poll::Ready(Some(HashMap::new()));
+            }
+            trace!("resolving");
+            let resolving = this.resolver.call(this.name.clone());
+            this.resolving.set(Some(resolving));
+        }
+    }
+}
+
+impl<R, N> fmt::Debug for Resolutions<R, N>
+where
+    R: Service<N> + fmt::Debug,
+    N: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolutions")
+            .field("resolver", &self.resolver)
+            .field("name", &self.name)
+            .field("interval", &self.interval)
+            .field("jitter", &self.jitter)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use tokio::time;
+    use tokio_test::{assert_pending, assert_ready, task};
+    use tower_test::mock;
+
+    use super::*;
+
+    /// Resolves every name to the addresses that it was last given, or fails if it was given none.
+    #[derive(Clone, Default)]
+    struct Resolver(Arc<Mutex<Vec<SocketAddr>>>);
+
+    impl Resolver {
+        fn set(&self, ports: &[u16]) {
+            *self.0.lock().unwrap() = ports.iter().map(|p| ([10, 0, 0, 1], *p).into()).collect();
+        }
+    }
+
+    impl Service<&'static str> for Resolver {
+        type Response = Vec<SocketAddr>;
+        type Error = &'static str;
+        type Future = futures_util::future::Ready<Result<Vec<SocketAddr>, &'static str>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, _: &'static str) -> Self::Future {
+            let addrs = self.0.lock().unwrap().clone();
+            futures_util::future::ready(if addrs.is_empty() {
+                Err("no addresses")
+            } else {
+                Ok(addrs)
+            })
+        }
+    }
+
+    /// Resolves names with a [`Resolver`] until it is broken, after which it fails to become ready.
+    #[derive(Clone, Default)]
+    struct Breakable {
+        resolver: Resolver,
+        broken: Arc<Mutex<bool>>,
+        // The number of times that it failed to become ready.
+        failures: Arc<Mutex<usize>>,
+    }
+
+    impl Service<&'static str> for Breakable {
+        type Response = Vec<SocketAddr>;
+        type Error = &'static str;
+        type Future = <Resolver as Service<&'static str>>::Future;
+
+        fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+            if *self.broken.lock().unwrap() {
+                *self.failures.lock().unwrap() += 1;
+                return Poll::Ready(Err("resolver broken"));
+            }
+            self.resolver.poll_ready(cx)
+        }
+
+        fn call(&mut self, name: &'static str) -> Self::Future {
+            self.resolver.call(name)
+        }
+    }
+
+    fn describe(change: Change<SocketAddr, ()>) -> String {
+        match change {
+            Change::Insert(addr, ()) => format!("+{}", addr.port()),
+            Change::Remove(addr) => format!("-{}", addr.port()),
+        }
+    }
+
+    /// Polls `resolve` until it builds no more services, returning its changes in order of port.
+    fn changes<R>(
+        resolve: &mut task::Spawn<Resolve<R, &'static str, mock::Mock<SocketAddr, ()>>>,
+        handle: &mut mock::Handle<SocketAddr, ()>,
+    ) -> Vec<String>
+    where
+        R: Service<&'static str, Response = Vec<SocketAddr>>,
+        R::Error: Into<crate::BoxError>,
+    {
+        let mut changes = Vec::new();
+        loop {
+            match resolve.poll_next() {
+                Poll::Ready(Some(change)) => changes.push(describe(change.unwrap())),
+                Poll::Ready(None) => panic!("resolution ended"),
+                Poll::Pending => match handle.poll_request() {
+                    Poll::Ready(Some((_, rsp))) => rsp.send_response(()),
+                    _ => break,
+                },
+            }
+        }
+        changes.sort();
+        changes
+    }
+
+    #[tokio::test]
+    async fn refreshes_addresses() {
+        time::pause();
+
+        let resolver = Resolver::default();
+        resolver.set(&[1, 2]);
+        let (make, mut handle) = mock::pair();
+        let resolve = Resolve::new(resolver.clone(), "svc", make)
+            .with_interval(Duration::from_secs(10))
+            .with_jitter(0.0);
+        let mut resolve = task::spawn(resolve);
+
+        assert_eq!(changes(&mut resolve, &mut handle), ["+1", "+2"]);
+
+        // Changes are only picked up once the interval has elapsed.
+        resolver.set(&[2, 3]);
+        time::sleep(Duration::from_secs(9)).await;
+        assert_pending!(resolve.poll_next());
+        time::sleep(Duration::from_secs(1)).await;
+        assert_eq!(changes(&mut resolve, &mut handle), ["+3", "-1"]);
+    }
+
+    #[tokio::test]
+    async fn expires_addresses_after_ttl() {
+        time::pause();
+
+        let resolver = Resolver::default();
+        resolver.set(&[1]);
+        let (make, mut handle) = mock::pair();
+        let resolve = Resolve::new(resolver.clone(), "svc", make)
+            .with_interval(Duration::from_secs(10))
+            .with_jitter(0.0)
+            .with_ttl(Duration::from_secs(25));
+        let mut resolve = task::spawn(resolve);
+        assert_eq!(changes(&mut resolve, &mut handle), ["+1"]);
+
+        // Failed resolutions keep the last addresses until they expire.
+        resolver.set(&[]);
+        time::sleep(Duration::from_secs(20)).await;
+        assert!(changes(&mut resolve, &mut handle).is_empty());
+        time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(changes(&mut resolve, &mut handle), ["-1"]);
+
+        resolver.set(&[1]);
+        time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(changes(&mut resolve, &mut handle), ["+1"]);
+        assert_pending!(resolve.poll_next());
+    }
+
+    #[tokio::test]
+    async fn ends_when_resolver_fails() {
+        time::pause();
+
+        let resolver = Breakable::default();
+        resolver.resolver.set(&[1]);
+        let (make, mut handle) = mock::pair();
+        let resolve = Resolve::new(resolver.clone(), "svc", make)
+            .with_interval(Duration::from_secs(10))
+            .with_jitter(0.0);
+        let mut resolve = task::spawn(resolve);
+        assert_eq!(changes(&mut resolve, &mut handle), ["+1"]);
+
+        // A resolver that fails isn't used again, so its addresses are removed for good.
+        *resolver.broken.lock().unwrap() = true;
+        time::sleep(Duration::from_secs(10)).await;
+        let change = assert_ready!(resolve.poll_next()).unwrap().unwrap();
+        assert_eq!(describe(change), "-1");
+        assert!(assert_ready!(resolve.poll_next()).is_none());
+        time::sleep(Duration::from_secs(10)).await;
+        assert!(assert_ready!(resolve.poll_next()).is_none());
+        assert_eq!(*resolver.failures.lock().unwrap(), 1);
+    }
+}
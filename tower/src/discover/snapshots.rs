@@ -0,0 +1,483 @@
+use super::Change;
+use futures_core::{ready, Stream};
+use futures_util::future::BoxFuture;
+use futures_util::stream::FuturesUnordered;
+use pin_project_lite::pin_project;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::Hash;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::sync::watch;
+use tower_service::Service;
+use tracing::trace;
+
+pin_project! {
+    /// Turns a stream of complete endpoint sets into a [`Discover`].
+    ///
+    /// Many service registries deliver the full set of endpoints every time it changes, rather
+    /// than the endpoints that were added or removed. [`Snapshots`] takes a stream of such
+    /// snapshots, each a [`HashMap`] from endpoint keys to targets, and diffs every snapshot
+    /// against the previous one:
+    ///
+    /// - When a key appears, a service is built for its target with `make`, which is typically a
+    ///   [`MakeService`], and a [`Change::Insert`] is yielded once it is built.
+    /// - When a key disappears, a [`Change::Remove`] is yielded.
+    /// - When the target of a key changes, a new service is built for it, and a
+    ///   [`Change::Insert`] is yielded with the same key once it is built. Until then, the
+    ///   service built for the previous target remains in use. Consumers of the [`Discover`] must
+    ///   treat such an insert as replacing the existing service, as the balancers in this crate do.
+    /// - Keys whose target is unchanged yield nothing.
+    ///
+    /// Services are built concurrently. If a key's target changes or the key disappears while its
+    /// service is being built, the service is discarded when it is built.
+    ///
+    /// If building a service fails, the error is yielded, and the target is built again when a
+    /// later snapshot contains it. If the key already had a service, that service remains in use
+    /// until the key disappears or a service is built for it.
+    ///
+    /// The stream ends when the snapshot stream ends and all pending services have been built.
+    /// A [`tokio::sync::watch`] receiver can be used as the snapshot stream with
+    /// [`Snapshots::from_watch`].
+    ///
+    /// [`Discover`]: crate::discover::Discover
+    /// [`MakeService`]: crate::MakeService
+    pub struct Snapshots<St, M, K, T>
+    where
+        M: Service<T>,
+    {
+        #[pin]
+        snapshots: St,
+        snapshots_done: bool,
+        make: M,
+        // The targets of the latest snapshot, excluding those whose services failed to build.
+        targets: HashMap<K, T>,
+        // Keys that have been yielded in a `Change::Insert` and not yet removed.
+        inserted: HashSet<K>,
+        // The generation of the service that is being built for each key.
+        building: HashMap<K, u64>,
+        generation: u64,
+        // Keys whose services are waiting for `make` to become ready.
+        unstarted: VecDeque<(K, u64)>,
+        making: FuturesUnordered<Making<K, M::Future>>,
+        changes: VecDeque<Change<K, M::Response>>,
+    }
+}
+
+pin_project! {
+    /// A service that is being built for a key.
+    struct Making<K, F> {
+        #[pin]
+        future: F,
+        key: Option<K>,
+        generation: u64,
+    }
+}
+
+/// A stream of the values of a [`watch::Receiver`], starting with its current value.
+///
+/// This is the snapshot stream used by [`Snapshots::from_watch`].
+pub struct WatchSnapshots<T> {
+    unseen: Option<watch::Receiver<T>>,
+    changed: Option<BoxFuture<'static, (Result<(), watch::error::RecvError>, watch::Receiver<T>)>>,
+}
+
+// ===== impl Snapshots =====
+
+impl<St, M, K, T> Snapshots<St, M, K, T>
+where
+    St: Stream<Item = HashMap<K, T>>,
+    M: Service<T>,
+    K: Hash + Eq + Clone,
+    T: PartialEq + Clone,
+{
+    /// Diffs the endpoint sets yielded by `snapshots`, building services for new targets with
+    /// `make`.
+    pub fn new(snapshots: St, make: M) -> Self {
+        Self {
+            snapshots,
+            snapshots_done: false,
+            make,
+            targets: HashMap::new(),
+            inserted: HashSet::new(),
+            building: HashMap::new(),
+            generation: 0,
+            unstarted: VecDeque::new(),
+            making: FuturesUnordered::new(),
+            changes: VecDeque::new(),
+        }
+    }
+}
+
+impl<M, K, T> Snapshots<WatchSnapshots<HashMap<K, T>>, M, K, T>
+where
+    M: Service<T>,
+    K: Hash + Eq + Clone + Send + Sync + 'static,
+    T: PartialEq + Clone + Send + Sync + 'static,
+{
+    /// Diffs the endpoint sets published to `snapshots`, building services for new targets with
+    /// `make`.
+    ///
+    /// The current endpoint set is diffed first. Intermediate endpoint sets that are replaced
+    /// before they are observed are skipped.
+    pub fn from_watch(snapshots: watch::Receiver<HashMap<K, T>>, make: M) -> Self {
+        Self::new(WatchSnapshots::new(snapshots), make)
+    }
+}
+
+impl<St, M, K, T> Snapshots<St, M, K, T>
+where
+    M: Service<T>,
+    K: Hash + Eq + Clone,
+    T: PartialEq + Clone,
+{
+    /// Returns a mutable reference to the snapshot stream.
+    pub(super) fn snapshots_mut(&mut self) -> &mut St {
+        &mut self.snapshots
+    }
+
+    /// Returns a pinned mutable reference to the snapshot stream.
+    pub(super) fn snapshots_pin_mut(self: Pin<&mut Self>) -> Pin<&mut St> {
+        self.project().snapshots
+    }
+
+    /// Diffs `snapshot` against the current targets.
+    fn update(self: Pin<&mut Self>, snapshot: HashMap<K, T>) {
+        let this = self.project();
+
+        // A key that was inserted may have no target, if building its latest target failed.
+        let removed = this
+            .targets
+            .keys()
+            .chain(this.inserted.iter())
+            .chain(this.building.keys())
+            .filter(|key| !snapshot.contains_key(*key))
+            .cloned()
+            .collect::<HashSet<_>>();
+        for key in removed {
+            this.targets.remove(&key);
+            this.building.remove(&key);
+            if this.inserted.remove(&key) {
+                trace!("endpoint removed");
+                this.changes.push_back(Change::Remove(key));
+            }
+        }
+
+        for (key, target) in snapshot {
+            if this.targets.get(&key) == Some(&target) {
+                continue;
+            }
+            trace!("building endpoint");
+            *this.generation += 1;
+            this.building.insert(key.clone(), *this.generation);
+            this.unstarted.push_back((key.clone(), *this.generation));
+            this.targets.insert(key, target);
+        }
+    }
+
+    /// Returns whether `generation` is the latest service being built for `key`.
+    fn is_current(building: &HashMap<K, u64>, key: &K, generation: u64) -> bool {
+        building.get(key) == Some(&generation)
+    }
+}
+
+impl<St, M, K, T> Stream for Snapshots<St, M, K, T>
+where
+    St: Stream<Item = HashMap<K, T>>,
+    M: Service<T>,
+    K: Hash + Eq + Clone,
+    T: PartialEq + Clone,
+{
+    type Item = Result<Change<K, M::Response>, M::Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(change) = self.as_mut().project().changes.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            // Diff every snapshot that is available.
+            while !self.snapshots_done {
+                match self.as_mut().project().snapshots.poll_next(cx) {
+                    Poll::Ready(Some(snapshot)) => self.as_mut().update(snapshot),
+                    Poll::Ready(None) => *self.as_mut().project().snapshots_done = true,
+                    Poll::Pending => break,
+                }
+            }
+            if !self.changes.is_empty() {
+                continue;
+            }
+
+            // Start building services as `make` becomes ready.
+            let this = self.as_mut().project();
+            while let Some((key, generation)) = this.unstarted.front() {
+                if !Self::is_current(this.building, key, *generation) {
+                    this.unstarted.pop_front();
+                    continue;
+                }
+                match this.make.poll_ready(cx) {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(e))),
+                    // `make` may only become ready once services that are being built are built,
+                    // as with a concurrency limit, so they are polled regardless.
+                    Poll::Pending => break,
+                }
+                let (key, generation) = this.unstarted.pop_front().expect("must be present");
+                let target = this.targets.get(&key).expect("must have target").clone();
+                this.making.push(Making {
+                    future: this.make.call(target),
+                    key: Some(key),
+                    generation,
+                });
+            }
+
+            // Yield services as they are built.
+            match ready!(Pin::new(&mut *this.making).poll_next(cx)) {
+                Some((key, generation, result)) => {
+                    if !Self::is_current(this.building, &key, generation) {
+                        trace!("discarding outdated endpoint");
+                        continue;
+                    }
+                    this.building.remove(&key);
+                    match result {
+                        Ok(service) => {
+                            this.inserted.insert(key.clone());
+                            return Poll::Ready(Some(Ok(Change::Insert(key, service))));
+                        }
+                        Err(e) => {
+                            // Build the target again when a later snapshot contains it.
+                            this.targets.remove(&key);
+                            return Poll::Ready(Some(Err(e)));
+                        }
+                    }
+                }
+                None if *this.snapshots_done && this.unstarted.is_empty() => {
+                    return Poll::Ready(None)
+                }
+                None => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<St, M, K, T> fmt::Debug for Snapshots<St, M, K, T>
+where
+    St: fmt::Debug,
+    M: Service<T> + fmt::Debug,
+    K: fmt::Debug,
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Snapshots")
+            .field("snapshots", &self.snapshots)
+            .field("make", &self.make)
+            .field("targets", &self.targets)
+            .field("building", &self.building.len())
+            .finish()
+    }
+}
+
+// ===== impl Making =====
+
+impl<K, F, S, E> Future for Making<K, F>
+where
+    F: Future<Output = Result<S, E>>,
+{
+    type Output = (K, u64, Result<S, E>);
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.future.poll(cx));
+        let key = this.key.take().expect("polled after completion");
+        Poll::Ready((key, *this.generation, result))
+    }
+}
+
+// ===== impl WatchSnapshots =====
+
+impl<T> WatchSnapshots<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    /// Yields the current value of `rx`, and then every value that is published to it.
+    pub fn new(rx: watch::Receiver<T>) -> Self {
+        Self {
+            unseen: Some(rx),
+            changed: None,
+        }
+    }
+}
+
+impl<T> Stream for WatchSnapshots<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    type Item = T;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        loop {
+            if let Some(mut rx) = self.unseen.take() {
+                let value = rx.borrow().clone();
+                self.changed = Some(Box::pin(async move {
+                    let result = rx.changed().await;
+                    (result, rx)
+                }));
+                return Poll::Ready(Some(value));
+            }
+
+            let changed = match self.changed.as_mut() {
+                Some(changed) => changed,
+                None => return Poll::Ready(None),
+            };
+            match ready!(changed.as_mut().poll(cx)) {
+                (Ok(()), rx) => self.unseen = Some(rx),
+                // The sender was dropped, so no more values will be published.
+                (Err(_), _) => self.changed = None,
+            }
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for WatchSnapshots<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WatchSnapshots")
+            .field("unseen", &self.unseen)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_test::{assert_pending, assert_ready, task};
+    use tower_test::mock;
+
+    use super::*;
+
+    type Snapshot = HashMap<&'static str, u32>;
+
+    fn snapshot(targets: &[(&'static str, u32)]) -> Snapshot {
+        targets.iter().cloned().collect()
+    }
+
+    fn describe(change: Change<&'static str, &'static str>) -> String {
+        match change {
+            Change::Insert(key, service) => format!("+{}={}", key, service),
+            Change::Remove(key) => format!("-{}", key),
+        }
+    }
+
+    /// Builds each requested target into a service named after it.
+    async fn build(handle: &mut mock::Handle<u32, &'static str>, n: usize) {
+        for _ in 0..n {
+            let (target, rsp) = handle.next_request().await.unwrap();
+            rsp.send_response(["zero", "one", "two", "three"][target as usize]);
+        }
+    }
+
+    #[tokio::test]
+    async fn diffs_snapshots() {
+        let (tx, rx) = watch::channel(snapshot(&[("a", 1), ("b", 2)]));
+        let (make, mut handle) = mock::pair::<u32, &'static str>();
+        let mut discover = task::spawn(Snapshots::from_watch(rx, make));
+
+        assert_pending!(discover.poll_next());
+        build(&mut handle, 2).await;
+        let mut changes = vec![
+            describe(assert_ready!(discover.poll_next()).unwrap().unwrap()),
+            describe(assert_ready!(discover.poll_next()).unwrap().unwrap()),
+        ];
+        changes.sort();
+        assert_eq!(changes, ["+a=one", "+b=two"]);
+        assert_pending!(discover.poll_next());
+
+        // Unchanged targets are left alone.
+        tx.send(snapshot(&[("a", 1), ("c", 3)])).unwrap();
+        let change = assert_ready!(discover.poll_next()).unwrap().unwrap();
+        assert_eq!(describe(change), "-b");
+        assert_pending!(discover.poll_next());
+        build(&mut handle, 1).await;
+        let change = assert_ready!(discover.poll_next()).unwrap().unwrap();
+        assert_eq!(describe(change), "+c=three");
+
+        // Changed targets are rebuilt under the same key.
+        tx.send(snapshot(&[("a", 2), ("c", 3)])).unwrap();
+        assert_pending!(discover.poll_next());
+        build(&mut handle, 1).await;
+        let change = assert_ready!(discover.poll_next()).unwrap().unwrap();
+        assert_eq!(describe(change), "+a=two");
+
+        drop(tx);
+        assert!(assert_ready!(discover.poll_next()).is_none());
+    }
+
+    #[tokio::test]
+    async fn removes_keys_whose_rebuild_failed() {
+        let (tx, rx) = watch::channel(snapshot(&[("a", 1)]));
+        let (make, mut handle) = mock::pair::<u32, &'static str>();
+        let mut discover = task::spawn(Snapshots::from_watch(rx, make));
+        assert_pending!(discover.poll_next());
+        build(&mut handle, 1).await;
+        let change = assert_ready!(discover.poll_next()).unwrap().unwrap();
+        assert_eq!(describe(change), "+a=one");
+
+        // Rebuilding the changed target fails, so the previous service remains in use.
+        tx.send(snapshot(&[("a", 2)])).unwrap();
+        assert_pending!(discover.poll_next());
+        let (_, rsp) = handle.next_request().await.unwrap();
+        rsp.send_error("failed to build");
+        assert!(assert_ready!(discover.poll_next()).unwrap().is_err());
+
+        // The previous service is removed when the key disappears.
+        tx.send(snapshot(&[])).unwrap();
+        let change = assert_ready!(discover.poll_next()).unwrap().unwrap();
+        assert_eq!(describe(change), "-a");
+    }
+
+    #[cfg(feature = "limit")]
+    #[tokio::test]
+    async fn builds_services_while_make_is_not_ready() {
+        let (_tx, rx) = watch::channel(snapshot(&[("a", 1), ("b", 2)]));
+        let (make, mut handle) = mock::pair::<u32, &'static str>();
+        // `make` is only ready again once the service that is being built is built.
+        let make = crate::limit::ConcurrencyLimit::new(make, 1);
+        let mut discover = task::spawn(Snapshots::from_watch(rx, make));
+
+        let mut changes = Vec::new();
+        for _ in 0..2 {
+            assert_pending!(discover.poll_next());
+            build(&mut handle, 1).await;
+            assert!(discover.is_woken());
+            changes.push(describe(
+                assert_ready!(discover.poll_next()).unwrap().unwrap(),
+            ));
+        }
+        changes.sort();
+        assert_eq!(changes, ["+a=one", "+b=two"]);
+    }
+
+    #[tokio::test]
+    async fn discards_outdated_services() {
+        let (tx, rx) = watch::channel(snapshot(&[("a", 1)]));
+        let (make, mut handle) = mock::pair::<u32, &'static str>();
+        let mut discover = task::spawn(Snapshots::from_watch(rx, make));
+        assert_pending!(discover.poll_next());
+
+        // The target changes while its service is being built.
+        tx.send(snapshot(&[("a", 2)])).unwrap();
+        assert_pending!(discover.poll_next());
+        build(&mut handle, 2).await;
+        let change = assert_ready!(discover.poll_next()).unwrap().unwrap();
+        assert_eq!(describe(change), "+a=two");
+
+        // The key disappears while its service is being built.
+        tx.send(snapshot(&[("a", 2), ("b", 3)])).unwrap();
+        assert_pending!(discover.poll_next());
+        tx.send(snapshot(&[("a", 2)])).unwrap();
+        assert_pending!(discover.poll_next());
+        build(&mut handle, 1).await;
+        assert_pending!(discover.poll_next());
+    }
+}
@@ -0,0 +1,335 @@
+use super::{Change, Discover};
+use crate::hash::stable_hash;
+use futures_core::{ready, Stream};
+use pin_project_lite::pin_project;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::Hash;
+use std::{
+    fmt,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Limits a [`Discover`] to a stable subset of its services.
+    ///
+    /// When a client talks to a very large set of services, holding a connection to every one of
+    /// them is wasteful. [`Subset`] passes on at most `size` of the services that `discover` yields,
+    /// so that a balancer built over it only ever sees those.
+    ///
+    /// The subset is chosen by rendezvous (highest random weight) hashing: every service key is
+    /// scored by hashing it together with a client identifier, and the `size` keys with the highest
+    /// scores are selected. This has a few useful properties:
+    ///
+    /// - Clients with the same identifier choose the same subset, and clients with different
+    ///   identifiers choose independent subsets, so load spreads evenly across all services when
+    ///   there are many clients.
+    /// - When a service is added or removed, at most one other service enters or leaves the subset.
+    ///   Services that remain selected are never churned.
+    ///
+    /// Services that are not selected are held on to, since they may be selected later when the
+    /// set changes. Every time a service is selected, a clone of it is passed on, so the services
+    /// yielded by `discover` are typically cheap handles, such as connectors or lazily-connecting
+    /// clients, rather than established connections.
+    pub struct Subset<D>
+    where
+        D: Discover,
+    {
+        #[pin]
+        discover: D,
+        selection: Selection<D::Key, D::Service>,
+    }
+}
+
+/// The state of the subset, apart from the inner [`Discover`].
+struct Selection<K, S> {
+    seed: u64,
+    size: usize,
+    endpoints: HashMap<K, Endpoint<S>>,
+    // The keys of the services that are selected, and of those that aren't, ordered by rank.
+    selected: BTreeMap<Rank, K>,
+    standby: BTreeMap<Rank, K>,
+    next_id: u64,
+    changes: VecDeque<Change<K, S>>,
+}
+
+/// The score of a service, followed by the order in which it was discovered, which only tells
+/// services with the same score apart.
+type Rank = (u64, u64);
+
+struct Endpoint<S> {
+    rank: Rank,
+    service: S,
+}
+
+impl<D> Subset<D>
+where
+    D: Discover,
+{
+    /// Selects at most `size` of the services yielded by `discover`, choosing among them by
+    /// `client_id`.
+    ///
+    /// `client_id` should be distinct for every client that shares the same set of services (an
+    /// instance identifier, for example) and stable across restarts, so that a client keeps talking
+    /// to the same subset.
+    ///
+    /// # Panics
+    ///
+    /// If `size` is zero.
+    pub fn new<C: Hash>(discover: D, client_id: C, size: usize) -> Self {
+        assert!(size > 0, "subset size must be positive");
+        Self {
+            discover,
+            selection: Selection {
+                seed: stable_hash(&client_id),
+                size,
+                endpoints: HashMap::new(),
+                selected: BTreeMap::new(),
+                standby: BTreeMap::new(),
+                next_id: 0,
+                changes: VecDeque::new(),
+            },
+        }
+    }
+
+    /// Returns the number of services that are currently selected.
+    pub fn selected_len(&self) -> usize {
+        self.selection.selected.len()
+    }
+
+    /// Returns the number of services yielded by the inner [`Discover`], whether or not they are
+    /// selected.
+    pub fn len(&self) -> usize {
+        self.selection.endpoints.len()
+    }
+
+    /// Returns whether or not the inner [`Discover`] has yielded any services.
+    pub fn is_empty(&self) -> bool {
+        self.selection.endpoints.is_empty()
+    }
+}
+
+impl<D> fmt::Debug for Subset<D>
+where
+    D: Discover + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subset")
+            .field("discover", &self.discover)
+            .field("size", &self.selection.size)
+            .field("selected", &self.selection.selected.len())
+            .field("len", &self.selection.endpoints.len())
+            .finish()
+    }
+}
+
+impl<D> Stream for Subset<D>
+where
+    D: Discover,
+    D::Key: Hash + Clone,
+    D::Service: Clone,
+{
+    type Item = Result<Change<D::Key, D::Service>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        loop {
+            if let Some(change) = this.selection.changes.pop_front() {
+                return Poll::Ready(Some(Ok(change)));
+            }
+
+            match ready!(this.discover.as_mut().poll_discover(cx)) {
+                None => return Poll::Ready(None),
+                Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                Some(Ok(Change::Insert(key, service))) => this.selection.insert(key, service),
+                Some(Ok(Change::Remove(key))) => this.selection.remove(&key),
+            }
+        }
+    }
+}
+
+impl<K, S> Selection<K, S>
+where
+    K: Hash + Eq + Clone,
+    S: Clone,
+{
+    fn insert(&mut self, key: K, service: S) {
+        if let Some(endpoint) = self.endpoints.get_mut(&key) {
+            // The service is being replaced. If it is selected, pass the replacement on.
+            endpoint.service = service;
+            if self.selected.contains_key(&endpoint.rank) {
+                self.changes
+                    .push_back(Change::Insert(key, endpoint.service.clone()));
+            }
+            return;
+        }
+
+        let rank = (stable_hash(&(self.seed, &key)), self.next_id);
+        self.next_id += 1;
+        self.endpoints
+            .insert(key.clone(), Endpoint { rank, service });
+
+        if self.selected.len() < self.size {
+            self.select(rank, key);
+            return;
+        }
+
+        // The subset is full, so the new service only displaces the lowest-ranked selected
+        // service if it ranks higher.
+        match self.selected.keys().next().copied() {
+            Some(lowest) if rank > lowest => {
+                self.deselect(lowest);
+                self.select(rank, key);
+            }
+            _ => {
+                self.standby.insert(rank, key);
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &K) {
+        let endpoint = match self.endpoints.remove(key) {
+            Some(endpoint) => endpoint,
+            None => return,
+        };
+        if self.standby.remove(&endpoint.rank).is_some() {
+            return;
+        }
+
+        self.selected.remove(&endpoint.rank);
+        self.changes.push_back(Change::Remove(key.clone()));
+
+        // Fill the vacancy with the highest-ranked service that isn't selected, if there is one.
+        if let Some(highest) = self.standby.keys().next_back().copied() {
+            let key = self
+                .standby
+                .remove(&highest)
+                .expect("standby key must be present");
+            self.select(highest, key);
+        }
+    }
+
+    fn select(&mut self, rank: Rank, key: K) {
+        let endpoint = self
+            .endpoints
+            .get(&key)
+            .expect("selected endpoints must be tracked");
+        let service = endpoint.service.clone();
+        self.selected.insert(rank, key.clone());
+        self.changes.push_back(Change::Insert(key, service));
+    }
+
+    fn deselect(&mut self, rank: Rank) {
+        let key = self
+            .selected
+            .remove(&rank)
+            .expect("deselected endpoints must be selected");
+        self.standby.insert(rank, key.clone());
+        self.changes.push_back(Change::Remove(key));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use std::collections::HashSet;
+    use std::convert::Infallible;
+    use tokio_test::{assert_ready, task};
+
+    type Changes = Vec<Change<usize, &'static str>>;
+
+    /// Drains `subset`, returning the set of keys that are selected at the end.
+    fn selected<D>(subset: &mut task::Spawn<Subset<D>>, initial: HashSet<usize>) -> HashSet<usize>
+    where
+        D: Discover<Key = usize, Service = &'static str, Error = Infallible>,
+    {
+        let mut keys = initial;
+        while let Some(change) = assert_ready!(subset.poll_next()) {
+            match change.unwrap() {
+                Change::Insert(key, _) => {
+                    keys.insert(key);
+                }
+                Change::Remove(key) => {
+                    assert!(keys.remove(&key), "removed a key that was not selected");
+                }
+            }
+        }
+        keys
+    }
+
+    fn inserts(keys: std::ops::Range<usize>) -> Changes {
+        keys.map(|key| Change::Insert(key, "svc")).collect()
+    }
+
+    #[test]
+    fn selects_stable_subset() {
+        let changes = inserts(0..100);
+
+        let mut subset = task::spawn(Subset::new(
+            stream::iter(changes.clone().into_iter().map(Ok::<_, Infallible>)),
+            "client",
+            10,
+        ));
+        let first = selected(&mut subset, HashSet::new());
+        assert_eq!(first.len(), 10);
+        assert_eq!(subset.selected_len(), 10);
+        assert_eq!(subset.len(), 100);
+
+        // The same client selects the same subset, regardless of the order that services are
+        // discovered in.
+        let mut subset = task::spawn(Subset::new(
+            stream::iter(changes.into_iter().rev().map(Ok::<_, Infallible>)),
+            "client",
+            10,
+        ));
+        assert_eq!(selected(&mut subset, HashSet::new()), first);
+    }
+
+    #[test]
+    fn minimal_churn() {
+        let mut changes = inserts(0..50);
+        let mut subset = task::spawn(Subset::new(
+            stream::iter(changes.clone().into_iter().map(Ok::<_, Infallible>)),
+            "client",
+            5,
+        ));
+        let before = selected(&mut subset, HashSet::new());
+
+        // Removing a selected service replaces it with exactly one other service.
+        let removed = *before.iter().next().unwrap();
+        changes.push(Change::Remove(removed));
+        let mut subset = task::spawn(Subset::new(
+            stream::iter(changes.into_iter().map(Ok::<_, Infallible>)),
+            "client",
+            5,
+        ));
+        let after = selected(&mut subset, HashSet::new());
+        assert_eq!(after.len(), 5);
+        assert_eq!(
+            before.difference(&after).collect::<Vec<_>>(),
+            vec![&removed]
+        );
+        assert_eq!(after.difference(&before).count(), 1);
+    }
+
+    #[test]
+    fn replaces_selected_services() {
+        let changes = vec![
+            Change::Insert(0, "a"),
+            Change::Insert(0, "b"),
+            Change::Remove(0),
+        ];
+        let mut subset = task::spawn(Subset::new(
+            stream::iter(changes.into_iter().map(Ok::<_, Infallible>)),
+            "client",
+            1,
+        ));
+
+        let mut next = || assert_ready!(subset.poll_next()).map(Result::unwrap);
+        assert!(matches!(next(), Some(Change::Insert(0, "a"))));
+        assert!(matches!(next(), Some(Change::Insert(0, "b"))));
+        assert!(matches!(next(), Some(Change::Remove(0))));
+        assert!(next().is_none());
+    }
+}
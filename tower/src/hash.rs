@@ -0,0 +1,14 @@
+//! Stable hashing, shared by the discovery subset and the consistent-hashing balancer.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+/// Hashes a value.
+///
+/// `DefaultHasher::new` always uses the same keys, so hashes are consistent across all processes
+/// built from the same version of the standard library.
+pub(crate) fn stable_hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
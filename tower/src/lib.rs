@@ -170,6 +170,8 @@ pub mod buffer;
 pub mod discover;
 #[cfg(feature = "filter")]
 pub mod filter;
+#[cfg(feature = "discover")]
+pub(crate) mod hash;
 #[cfg(feature = "hedge")]
 pub mod hedge;
 #[cfg(feature = "limit")]
@@ -181,6 +183,8 @@ pub mod load_shed;
 
 #[cfg(feature = "make")]
 pub mod make;
+#[cfg(feature = "pool")]
+pub mod pool;
 #[cfg(feature = "ready-cache")]
 pub mod ready_cache;
 #[cfg(feature = "reconnect")]
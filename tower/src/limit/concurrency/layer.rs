@@ -37,17 +37,27 @@ impl<S> Layer<S> for ConcurrencyLimitLayer {
 #[derive(Debug, Clone)]
 pub struct GlobalConcurrencyLimitLayer {
     semaphore: Arc<Semaphore>,
+    max: usize,
 }
 
 impl GlobalConcurrencyLimitLayer {
     /// Create a new `GlobalConcurrencyLimitLayer`.
     pub fn new(max: usize) -> Self {
-        Self::with_semaphore(Arc::new(Semaphore::new(max)))
+        GlobalConcurrencyLimitLayer {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+        }
     }
 
     /// Create a new `GlobalConcurrencyLimitLayer` from a `Arc<Semaphore>`
+    ///
+    /// The number of permits available when the layer is created is taken to
+    /// be the limit when reporting the [`Load`] of the services it creates.
+    ///
+    /// [`Load`]: crate::load::Load
     pub fn with_semaphore(semaphore: Arc<Semaphore>) -> Self {
-        GlobalConcurrencyLimitLayer { semaphore }
+        let max = semaphore.available_permits();
+        GlobalConcurrencyLimitLayer { semaphore, max }
     }
 }
 
@@ -55,6 +65,6 @@ impl<S> Layer<S> for GlobalConcurrencyLimitLayer {
     type Service = ConcurrencyLimit<S>;
 
     fn layer(&self, service: S) -> Self::Service {
-        ConcurrencyLimit::with_semaphore(service, self.semaphore.clone())
+        ConcurrencyLimit::with_limit(service, self.semaphore.clone(), self.max)
     }
 }
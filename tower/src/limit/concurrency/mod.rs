@@ -8,3 +8,6 @@ pub use self::{
     layer::{ConcurrencyLimitLayer, GlobalConcurrencyLimitLayer},
     service::ConcurrencyLimit,
 };
+
+#[cfg(feature = "load")]
+pub use self::service::{Cost, PermitsInUse};
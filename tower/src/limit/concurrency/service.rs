@@ -15,6 +15,9 @@ use std::{
 pub struct ConcurrencyLimit<T> {
     inner: T,
     semaphore: PollSemaphore,
+    /// The limit that the semaphore enforces, used to report the number of
+    /// permits in use.
+    max: usize,
     /// The currently acquired semaphore permit, if there is sufficient
     /// concurrency to send a new request.
     ///
@@ -26,13 +29,30 @@ pub struct ConcurrencyLimit<T> {
 impl<T> ConcurrencyLimit<T> {
     /// Create a new concurrency limiter.
     pub fn new(inner: T, max: usize) -> Self {
-        Self::with_semaphore(inner, Arc::new(Semaphore::new(max)))
+        Self::with_limit(inner, Arc::new(Semaphore::new(max)), max)
     }
 
     /// Create a new concurrency limiter with a provided shared semaphore
+    ///
+    /// The number of permits available when the service is created is taken
+    /// to be the limit when reporting the permits in use with
+    /// [`PermitsInUse`], so the semaphore should not have any permits in use
+    /// yet. Services that share a semaphore are better created with a
+    /// [`GlobalConcurrencyLimitLayer`].
+    ///
+    /// [`PermitsInUse`]: crate::limit::concurrency::PermitsInUse
+    /// [`GlobalConcurrencyLimitLayer`]: crate::limit::GlobalConcurrencyLimitLayer
     pub fn with_semaphore(inner: T, semaphore: Arc<Semaphore>) -> Self {
+        let max = semaphore.available_permits();
+        Self::with_limit(inner, semaphore, max)
+    }
+
+    /// Create a new concurrency limiter with a semaphore that enforces a limit
+    /// of `max` permits.
+    pub(super) fn with_limit(inner: T, semaphore: Arc<Semaphore>, max: usize) -> Self {
         ConcurrencyLimit {
             inner,
+            max,
             semaphore: PollSemaphore::new(semaphore),
             permit: None,
         }
@@ -101,6 +121,7 @@ impl<T: Clone> Clone for ConcurrencyLimit<T> {
         Self {
             inner: self.inner.clone(),
             semaphore: self.semaphore.clone(),
+            max: self.max,
             permit: None,
         }
     }
@@ -116,3 +137,108 @@ where
         self.inner.load()
     }
 }
+
+/// A [`ConcurrencyLimit`] whose [`Load`] includes the number of permits in
+/// use.
+///
+/// When the semaphore is shared, as with a [`GlobalConcurrencyLimitLayer`],
+/// the permits in use are counted across every service that shares it. Their
+/// loads then only differ by the load of their inner services.
+///
+/// [`Load`]: crate::load::Load
+/// [`GlobalConcurrencyLimitLayer`]: crate::limit::GlobalConcurrencyLimitLayer
+#[cfg(feature = "load")]
+#[cfg_attr(docsrs, doc(cfg(feature = "load")))]
+#[derive(Clone, Debug)]
+pub struct PermitsInUse<S> {
+    inner: ConcurrencyLimit<S>,
+}
+
+#[cfg(feature = "load")]
+impl<S> PermitsInUse<S> {
+    /// Reports the number of permits in use by `inner` along with its load.
+    pub fn new(inner: ConcurrencyLimit<S>) -> Self {
+        Self { inner }
+    }
+
+    /// Get a reference to the inner service
+    pub fn get_ref(&self) -> &ConcurrencyLimit<S> {
+        &self.inner
+    }
+
+    /// Get a mutable reference to the inner service
+    pub fn get_mut(&mut self) -> &mut ConcurrencyLimit<S> {
+        &mut self.inner
+    }
+
+    /// Consume `self`, returning the inner service
+    pub fn into_inner(self) -> ConcurrencyLimit<S> {
+        self.inner
+    }
+}
+
+#[cfg(feature = "load")]
+impl<S, Request> Service<Request> for PermitsInUse<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        self.inner.call(request)
+    }
+}
+
+#[cfg(feature = "load")]
+impl<S> crate::load::Load for PermitsInUse<S>
+where
+    S: crate::load::Load,
+{
+    type Metric = Cost<S::Metric>;
+    fn load(&self) -> Self::Metric {
+        let limit = &self.inner;
+        Cost {
+            in_use: limit
+                .max
+                .saturating_sub(limit.semaphore.available_permits()),
+            inner: limit.inner.load(),
+        }
+    }
+}
+
+/// The load of a [`PermitsInUse`].
+///
+/// Loads are compared first by the number of permits in use, and then by the
+/// load of the inner service.
+#[cfg(feature = "load")]
+#[cfg_attr(docsrs, doc(cfg(feature = "load")))]
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Cost<M> {
+    in_use: usize,
+    inner: M,
+}
+
+#[cfg(feature = "load")]
+impl<M> Cost<M> {
+    /// Returns the number of permits that were in use, including permits
+    /// that were acquired by ready services that have not yet been called.
+    pub fn in_use(&self) -> usize {
+        self.in_use
+    }
+
+    /// Returns the load of the inner service.
+    pub fn inner(&self) -> &M {
+        &self.inner
+    }
+
+    /// Consumes `self`, returning the load of the inner service.
+    pub fn into_inner(self) -> M {
+        self.inner
+    }
+}
@@ -0,0 +1,365 @@
+//! A [`Load`] implementation that penalizes services whose requests fail.
+
+#[cfg(feature = "discover")]
+use crate::discover::{Change, Discover};
+#[cfg(feature = "discover")]
+use futures_core::Stream;
+
+use super::Load;
+use futures_core::ready;
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower_service::Service;
+use tracing::trace;
+
+/// The lowest success rate that a service's load is scaled by.
+///
+/// This bounds the penalty for a service whose requests all fail to 100 times its load.
+const MIN_SUCCESS_RATE: f64 = 0.01;
+
+/// Classifies the result of a request as a success or a failure.
+///
+/// This is implemented for closures that take a reference to a `Result` and return a `bool`.
+pub trait ClassifyFailure<T, E> {
+    /// Returns whether `result` should count as a failure.
+    fn is_failure(&mut self, result: &Result<T, E>) -> bool;
+}
+
+impl<F, T, E> ClassifyFailure<T, E> for F
+where
+    F: FnMut(&Result<T, E>) -> bool,
+{
+    fn is_failure(&mut self, result: &Result<T, E>) -> bool {
+        self(result)
+    }
+}
+
+/// Scales the load of a service by the rate at which its requests fail.
+///
+/// Latency-based load estimators such as [`PeakEwma`] record every response, including failures.
+/// A service that fails requests quickly therefore appears to be very lightly loaded, and a
+/// balancer sends it more and more requests, all of which fail.
+///
+/// [`FailureEwma`] wraps another [`Load`] implementation and tracks an exponentially-weighted
+/// moving average (EWMA) of the failure rate of its requests, using a [`ClassifyFailure`] to decide
+/// which results are failures. Each result moves the failure rate towards 1.0 (for a failure) or
+/// 0.0 (for a success) by `weight`. Between results, the failure rate decays towards 0.0 over a
+/// period of `decay`, so that a service that is no longer sent requests is eventually tried again.
+///
+/// The inner load is offset by one and divided by the success rate, and the [`Cost`] is computed as
+/// `(load + 1) / (1 - failure_rate) - 1`. The success rate is never taken to be less than 0.01.
+///
+/// The inner load metric must be convertible into an [`f64`]; this is implemented for the metrics
+/// of [`PeakEwma`] and [`PendingRequests`].
+///
+/// [`PeakEwma`]: crate::load::PeakEwma
+/// [`PendingRequests`]: crate::load::PendingRequests
+#[derive(Debug)]
+pub struct FailureEwma<S, C> {
+    service: S,
+    classify: C,
+    rate: Arc<Mutex<FailureRate>>,
+}
+
+#[cfg(feature = "discover")]
+pin_project! {
+    /// Wraps a `D`-typed stream of discovered services with [`FailureEwma`].
+    #[cfg_attr(docsrs, doc(cfg(feature = "discover")))]
+    #[derive(Debug)]
+    pub struct FailureEwmaDiscover<D, C> {
+        #[pin]
+        discover: D,
+        classify: C,
+        weight: f64,
+        decay: Duration,
+    }
+}
+
+pin_project! {
+    /// Response future for [`FailureEwma`].
+    #[derive(Debug)]
+    pub struct ResponseFuture<F, C> {
+        #[pin]
+        inner: F,
+        classify: C,
+        rate: Arc<Mutex<FailureRate>>,
+    }
+}
+
+/// The load of a service, scaled by its failure rate.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Cost(f64);
+
+/// Holds the current failure rate estimate and the last time it was updated.
+#[derive(Debug)]
+struct FailureRate {
+    rate: f64,
+    update_at: Instant,
+    weight: f64,
+    decay_ns: f64,
+}
+
+// ===== impl FailureEwma =====
+
+impl<S, C> FailureEwma<S, C> {
+    /// Wraps an `S`-typed service so that its load is scaled up by the rate at which its requests
+    /// fail, as classified by `classify`.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is not between 0.0 (exclusive) and 1.0, or if `decay` is zero.
+    pub fn new(service: S, classify: C, weight: f64, decay: Duration) -> Self {
+        Self {
+            service,
+            classify,
+            rate: Arc::new(Mutex::new(FailureRate::new(weight, decay))),
+        }
+    }
+
+    /// Returns the current failure rate estimate of the service, between 0.0 and 1.0.
+    pub fn failure_rate(&self) -> f64 {
+        self.rate.lock().expect("failure rate").decay()
+    }
+}
+
+impl<S, C> Load for FailureEwma<S, C>
+where
+    S: Load,
+    S::Metric: Into<f64>,
+{
+    type Metric = Cost;
+
+    fn load(&self) -> Cost {
+        let load = self.service.load().into();
+        let failure_rate = self.failure_rate();
+        let cost = Cost((load + 1.0) / (1.0 - failure_rate).max(MIN_SUCCESS_RATE) - 1.0);
+        trace!(load, failure_rate, ?cost, "failure ewma");
+        cost
+    }
+}
+
+impl<S, C, Request> Service<Request> for FailureEwma<S, C>
+where
+    S: Service<Request>,
+    C: ClassifyFailure<S::Response, S::Error> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, C>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        ResponseFuture {
+            inner: self.service.call(req),
+            classify: self.classify.clone(),
+            rate: self.rate.clone(),
+        }
+    }
+}
+
+// ===== impl FailureEwmaDiscover =====
+
+#[cfg(feature = "discover")]
+impl<D, C> FailureEwmaDiscover<D, C> {
+    /// Wraps a `D`-typed [`Discover`] so that services are penalized for failing requests.
+    ///
+    /// See [`FailureEwma::new`] for the meaning of the parameters.
+    ///
+    /// # Panics
+    ///
+    /// If `weight` is not between 0.0 (exclusive) and 1.0, or if `decay` is zero.
+    pub fn new(discover: D, classify: C, weight: f64, decay: Duration) -> Self
+    where
+        D: Discover,
+    {
+        FailureRate::check(weight, decay);
+        Self {
+            discover,
+            classify,
+            weight,
+            decay,
+        }
+    }
+}
+
+#[cfg(feature = "discover")]
+impl<D, C> Stream for FailureEwmaDiscover<D, C>
+where
+    D: Discover,
+    C: Clone,
+{
+    type Item = Result<Change<D::Key, FailureEwma<D::Service, C>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Remove(k)) => Change::Remove(k),
+            Some(Change::Insert(k, svc)) => {
+                let failure_ewma =
+                    FailureEwma::new(svc, this.classify.clone(), *this.weight, *this.decay);
+                Change::Insert(k, failure_ewma)
+            }
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, C, T, E> Future for ResponseFuture<F, C>
+where
+    F: Future<Output = Result<T, E>>,
+    C: ClassifyFailure<T, E>,
+{
+    type Output = Result<T, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        let failed = this.classify.is_failure(&result);
+        if let Ok(mut rate) = this.rate.lock() {
+            rate.update(failed);
+        }
+        Poll::Ready(result)
+    }
+}
+
+// ===== impl FailureRate =====
+
+impl FailureRate {
+    fn check(weight: f64, decay: Duration) {
+        assert!(
+            0.0 < weight && weight <= 1.0,
+            "weight must be between 0.0 (exclusive) and 1.0"
+        );
+        assert!(decay > Duration::from_secs(0), "decay must be positive");
+    }
+
+    fn new(weight: f64, decay: Duration) -> Self {
+        Self::check(weight, decay);
+        Self {
+            rate: 0.0,
+            update_at: Instant::now(),
+            weight,
+            decay_ns: decay.as_nanos() as f64,
+        }
+    }
+
+    /// Decays the failure rate towards zero according to how much time has elapsed since the last
+    /// update.
+    fn decay(&mut self) -> f64 {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.update_at).as_nanos() as f64;
+        self.rate *= (-elapsed / self.decay_ns).exp();
+        self.update_at = now;
+        self.rate
+    }
+
+    /// Records the outcome of a request.
+    fn update(&mut self, failed: bool) {
+        let prior = self.decay();
+        let outcome = if failed { 1.0 } else { 0.0 };
+        self.rate = prior + (outcome - prior) * self.weight;
+        trace!(failed, prior, rate = self.rate, "update failure rate");
+    }
+}
+
+// ===== impl Cost =====
+
+impl From<Cost> for f64 {
+    fn from(Cost(cost): Cost) -> f64 {
+        cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future;
+    use std::time::Duration;
+    use tokio::time;
+    use tokio_test::{assert_ready, task};
+
+    use super::*;
+    use crate::load::Constant;
+
+    /// Fails requests that ask it to.
+    struct Svc;
+    impl Service<bool> for Svc {
+        type Response = ();
+        type Error = ();
+        type Future = future::Ready<Result<(), ()>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, fail: bool) -> Self::Future {
+            if fail {
+                future::err(())
+            } else {
+                future::ok(())
+            }
+        }
+    }
+
+    fn is_err(result: &Result<(), ()>) -> bool {
+        result.is_err()
+    }
+
+    #[tokio::test]
+    async fn failures_increase_cost() {
+        time::pause();
+
+        let mut svc =
+            FailureEwma::new(Constant::new(Svc, 1.0), is_err, 0.5, Duration::from_secs(1));
+        assert_eq!(svc.load(), Cost(1.0));
+
+        let _ = assert_ready!(task::spawn(svc.call(true)).poll());
+        assert_eq!(svc.failure_rate(), 0.5);
+        assert_eq!(svc.load(), Cost(3.0));
+
+        let _ = assert_ready!(task::spawn(svc.call(true)).poll());
+        assert_eq!(svc.failure_rate(), 0.75);
+        assert_eq!(svc.load(), Cost(7.0));
+
+        let _ = assert_ready!(task::spawn(svc.call(false)).poll());
+        assert_eq!(svc.failure_rate(), 0.375);
+    }
+
+    #[tokio::test]
+    async fn penalty_is_bounded() {
+        time::pause();
+
+        let mut svc =
+            FailureEwma::new(Constant::new(Svc, 0.0), is_err, 1.0, Duration::from_secs(1));
+        let _ = assert_ready!(task::spawn(svc.call(true)).poll());
+        assert_eq!(svc.failure_rate(), 1.0);
+        assert_eq!(svc.load(), Cost(99.0));
+    }
+
+    #[tokio::test]
+    async fn failure_rate_decays() {
+        time::pause();
+
+        let mut svc =
+            FailureEwma::new(Constant::new(Svc, 0.0), is_err, 1.0, Duration::from_secs(1));
+        let _ = assert_ready!(task::spawn(svc.call(true)).poll());
+
+        time::advance(Duration::from_secs(1)).await;
+        let rate = svc.failure_rate();
+        assert!(0.36 < rate && rate < 0.37, "rate={}", rate);
+
+        time::advance(Duration::from_secs(10)).await;
+        assert!(svc.failure_rate() < 0.0001);
+    }
+}
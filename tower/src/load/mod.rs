@@ -6,6 +6,14 @@
 //! - [`Constant`] — Always returns the same constant load value for a service.
 //! - [`PendingRequests`] — Measures load by tracking the number of in-flight requests.
 //! - [`PeakEwma`] — Measures load using a moving average of the peak latency for the service.
+//! - [`Reported`] — Measures load using the load that the service reports in its responses.
+//!
+//! Other wrapper types adjust the load reported by another [`Load`] implementation:
+//!
+//! - [`SlowStart`] — Makes newly added services appear more loaded, so that they receive a
+//!   gradually increasing share of requests.
+//! - [`FailureEwma`] — Makes services whose requests fail appear more loaded, so that services
+//!   that fail quickly don't attract requests.
 //!
 //! In general, you will want to use one of these when using the types in [`tower::balance`] which
 //! balance services depending on their load. Which load metric to use depends on your exact
@@ -61,18 +69,28 @@
 
 pub mod completion;
 mod constant;
+pub mod failure_ewma;
 pub mod peak_ewma;
 pub mod pending_requests;
+pub mod reported;
+pub mod slow_start;
 
 pub use self::{
     completion::{CompleteOnResponse, TrackCompletion},
     constant::Constant,
+    failure_ewma::FailureEwma,
     peak_ewma::PeakEwma,
     pending_requests::PendingRequests,
+    reported::Reported,
+    slow_start::SlowStart,
 };
 
 #[cfg(feature = "discover")]
-pub use self::{peak_ewma::PeakEwmaDiscover, pending_requests::PendingRequestsDiscover};
+pub use self::{
+    failure_ewma::FailureEwmaDiscover, peak_ewma::PeakEwmaDiscover,
+    pending_requests::PendingRequestsDiscover, reported::ReportedDiscover,
+    slow_start::SlowStartDiscover,
+};
 
 /// Types that implement this trait can give an estimate of how loaded they are.
 ///
@@ -296,6 +296,12 @@ impl Drop for Handle {
 
 // ===== impl Cost =====
 
+impl From<Cost> for f64 {
+    fn from(Cost(cost): Cost) -> f64 {
+        cost
+    }
+}
+
 // Utility that converts durations to nanos in f64.
 //
 // Due to a lossy transformation, the maximum value that can be represented is ~585 years,
@@ -137,6 +137,14 @@ where
     }
 }
 
+// ==== impl Count ====
+
+impl From<Count> for f64 {
+    fn from(Count(count): Count) -> f64 {
+        count as f64
+    }
+}
+
 // ==== RefCount ====
 
 impl RefCount {
@@ -0,0 +1,374 @@
+//! A `Load` implementation that uses the load reported by the service itself.
+
+#[cfg(feature = "discover")]
+use crate::discover::{Change, Discover};
+#[cfg(feature = "discover")]
+use futures_core::Stream;
+
+use super::Load;
+use futures_core::ready;
+use pin_project_lite::pin_project;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower_service::Service;
+use tracing::trace;
+
+/// Extracts a load report from a response.
+///
+/// This is implemented for closures that take a reference to a response and return an
+/// `Option<f64>`.
+pub trait ExtractLoad<Rsp> {
+    /// Returns the load that the service reported with `rsp`, if it reported any.
+    ///
+    /// Lesser values indicate that the service is less loaded. The value is typically a measure of
+    /// the service's utilization, such as its CPU usage or the depth of its request queue.
+    fn extract_load(&mut self, rsp: &Rsp) -> Option<f64>;
+}
+
+impl<F, Rsp> ExtractLoad<Rsp> for F
+where
+    F: FnMut(&Rsp) -> Option<f64>,
+{
+    fn extract_load(&mut self, rsp: &Rsp) -> Option<f64> {
+        self(rsp)
+    }
+}
+
+/// Measures the load of the underlying service using the load that the service reports in its
+/// responses.
+///
+/// [`Reported`] extracts a load report from every successful response using an [`ExtractLoad`].
+/// The most recent report is used as the service's load, and decays towards a default value as
+/// time passes without a fresh report, so that a service that reported a high load is eventually
+/// tried again. The default value is also used before the service has reported any load.
+///
+/// Reports only reflect the service's load as of the last response. To account for requests that
+/// have been sent since, the report can be multiplied by the number of pending requests plus one,
+/// similarly to [`PeakEwma`](super::PeakEwma), using [`Reported::with_pending_requests`].
+#[derive(Debug)]
+pub struct Reported<S, E> {
+    service: S,
+    extract: E,
+    report: Arc<Mutex<Report>>,
+    default: f64,
+    decay_ns: f64,
+    pending_requests: bool,
+}
+
+#[cfg(feature = "discover")]
+pin_project! {
+    /// Wraps a `D`-typed stream of discovered services with [`Reported`].
+    #[cfg_attr(docsrs, doc(cfg(feature = "discover")))]
+    #[derive(Debug)]
+    pub struct ReportedDiscover<D, E> {
+        #[pin]
+        discover: D,
+        extract: E,
+        default: f64,
+        decay: Duration,
+        pending_requests: bool,
+    }
+}
+
+pin_project! {
+    /// Response future for [`Reported`].
+    #[derive(Debug)]
+    pub struct ResponseFuture<F, E> {
+        #[pin]
+        inner: F,
+        extract: E,
+        // Held while the request is pending, so that pending requests can be counted.
+        report: Option<Arc<Mutex<Report>>>,
+    }
+}
+
+/// Represents the load reported by a service.
+///
+/// The underlying value is the most recently reported load, decayed towards the default load and,
+/// if enabled, scaled by the number of pending requests plus one.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Cost(f64);
+
+/// Holds the most recent load report and when it was received.
+#[derive(Debug)]
+struct Report {
+    load: f64,
+    reported_at: Instant,
+}
+
+// ===== impl Reported =====
+
+impl<S, E> Reported<S, E> {
+    /// Wraps an `S`-typed service so that its load is the load extracted from its responses by
+    /// `extract`.
+    ///
+    /// Until the service reports its load, and as reports age, its load is assumed to be
+    /// `default`. Reports decay towards `default` over a period of `decay`.
+    ///
+    /// # Panics
+    ///
+    /// If `decay` is zero.
+    pub fn new(service: S, extract: E, default: f64, decay: Duration) -> Self {
+        assert!(decay > Duration::from_secs(0), "decay must be positive");
+        Self {
+            service,
+            extract,
+            report: Arc::new(Mutex::new(Report {
+                load: default,
+                reported_at: Instant::now(),
+            })),
+            default,
+            decay_ns: decay.as_nanos() as f64,
+            pending_requests: false,
+        }
+    }
+
+    /// Scales the reported load by the number of pending requests plus one.
+    ///
+    /// The load is offset by one before it is scaled, so that pending requests add to the cost
+    /// of a service that reports no load.
+    pub fn with_pending_requests(mut self) -> Self {
+        self.pending_requests = true;
+        self
+    }
+}
+
+impl<S, E, Request> Service<Request> for Reported<S, E>
+where
+    S: Service<Request>,
+    E: ExtractLoad<S::Response> + Clone,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseFuture<S::Future, E>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        ResponseFuture {
+            inner: self.service.call(req),
+            extract: self.extract.clone(),
+            report: Some(self.report.clone()),
+        }
+    }
+}
+
+impl<S, E> Load for Reported<S, E> {
+    type Metric = Cost;
+
+    fn load(&self) -> Self::Metric {
+        let load = {
+            let report = self.report.lock().expect("reported load");
+            let elapsed = Instant::now().saturating_duration_since(report.reported_at);
+            let decay = (-(elapsed.as_nanos() as f64) / self.decay_ns).exp();
+            self.default + (report.load - self.default) * decay
+        };
+
+        let cost = if self.pending_requests {
+            let pending = Arc::strong_count(&self.report) - 1;
+            Cost((load + 1.0) * (pending + 1) as f64 - 1.0)
+        } else {
+            Cost(load)
+        };
+        trace!(load, ?cost, "reported load");
+        cost
+    }
+}
+
+// ===== impl ReportedDiscover =====
+
+#[cfg(feature = "discover")]
+impl<D, E> ReportedDiscover<D, E> {
+    /// Wraps a `D`-typed [`Discover`] so that services have a [`Reported`] load metric.
+    ///
+    /// See [`Reported::new`] for the meaning of the arguments.
+    ///
+    /// # Panics
+    ///
+    /// If `decay` is zero.
+    pub fn new<Request>(discover: D, extract: E, default: f64, decay: Duration) -> Self
+    where
+        D: Discover,
+        D::Service: Service<Request>,
+        E: ExtractLoad<<D::Service as Service<Request>>::Response>,
+    {
+        assert!(decay > Duration::from_secs(0), "decay must be positive");
+        ReportedDiscover {
+            discover,
+            extract,
+            default,
+            decay,
+            pending_requests: false,
+        }
+    }
+
+    /// Scales the load reported by each service by its number of pending requests plus one.
+    ///
+    /// See [`Reported::with_pending_requests`] for details.
+    pub fn with_pending_requests(mut self) -> Self {
+        self.pending_requests = true;
+        self
+    }
+}
+
+#[cfg(feature = "discover")]
+impl<D, E> Stream for ReportedDiscover<D, E>
+where
+    D: Discover,
+    E: Clone,
+{
+    type Item = Result<Change<D::Key, Reported<D::Service, E>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Remove(k)) => Change::Remove(k),
+            Some(Change::Insert(k, svc)) => {
+                let mut reported =
+                    Reported::new(svc, this.extract.clone(), *this.default, *this.decay);
+                reported.pending_requests = *this.pending_requests;
+                Change::Insert(k, reported)
+            }
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl ResponseFuture =====
+
+impl<F, E, T, Error> Future for ResponseFuture<F, E>
+where
+    F: Future<Output = Result<T, Error>>,
+    E: ExtractLoad<T>,
+{
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let result = ready!(this.inner.poll(cx));
+        let report = this.report.take().expect("polled after completion");
+        if let Ok(rsp) = &result {
+            match this.extract.extract_load(rsp) {
+                Some(load) if load.is_finite() => {
+                    if let Ok(mut report) = report.lock() {
+                        trace!(load, "load reported");
+                        report.load = load;
+                        report.reported_at = Instant::now();
+                    }
+                }
+                Some(load) => trace!(load, "ignoring invalid load report"),
+                None => {}
+            }
+        }
+        Poll::Ready(result)
+    }
+}
+
+// ===== impl Cost =====
+
+impl From<Cost> for f64 {
+    fn from(Cost(cost): Cost) -> f64 {
+        cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::future;
+    use std::time::Duration;
+    use tokio::time;
+    use tokio_test::{assert_ready_ok, task};
+
+    use super::*;
+
+    /// Responds with the load that it's asked to report.
+    struct Svc;
+    impl Service<Option<f64>> for Svc {
+        type Response = Option<f64>;
+        type Error = ();
+        type Future = future::Ready<Result<Option<f64>, ()>>;
+
+        fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), ()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn call(&mut self, req: Option<f64>) -> Self::Future {
+            future::ok(req)
+        }
+    }
+
+    fn extract(rsp: &Option<f64>) -> Option<f64> {
+        *rsp
+    }
+
+    #[tokio::test]
+    async fn report_decays_to_default() {
+        time::pause();
+
+        let mut svc = Reported::new(Svc, extract, 1.0, Duration::from_secs(1));
+        assert_eq!(svc.load(), Cost(1.0));
+
+        let mut rsp = task::spawn(svc.call(Some(11.0)));
+        assert_ready_ok!(rsp.poll());
+        assert_eq!(svc.load(), Cost(11.0));
+
+        // Responses without a report don't affect the load.
+        let mut rsp = task::spawn(svc.call(None));
+        assert_ready_ok!(rsp.poll());
+        assert_eq!(svc.load(), Cost(11.0));
+
+        time::advance(Duration::from_secs(1)).await;
+        let Cost(load) = svc.load();
+        assert!(4.0 < load && load < 5.0, "load={}", load);
+
+        time::advance(Duration::from_secs(10)).await;
+        let Cost(load) = svc.load();
+        assert!(1.0 < load && load < 1.001, "load={}", load);
+    }
+
+    #[tokio::test]
+    async fn pending_requests() {
+        time::pause();
+
+        let mut svc =
+            Reported::new(Svc, extract, 2.0, Duration::from_secs(1)).with_pending_requests();
+        assert_eq!(svc.load(), Cost(2.0));
+
+        let mut rsp0 = task::spawn(svc.call(Some(3.0)));
+        let mut rsp1 = task::spawn(svc.call(None));
+        assert_eq!(svc.load(), Cost(8.0));
+
+        assert_ready_ok!(rsp0.poll());
+        assert_eq!(svc.load(), Cost(7.0));
+
+        assert_ready_ok!(rsp1.poll());
+        assert_eq!(svc.load(), Cost(3.0));
+    }
+
+    #[tokio::test]
+    async fn pending_requests_without_load() {
+        time::pause();
+
+        let mut svc =
+            Reported::new(Svc, extract, 0.0, Duration::from_secs(1)).with_pending_requests();
+        let mut rsp = task::spawn(svc.call(Some(0.0)));
+        assert_ready_ok!(rsp.poll());
+        assert_eq!(svc.load(), Cost(0.0));
+
+        // Pending requests add to the cost, even though the service reports no load.
+        let _rsp0 = svc.call(None);
+        let _rsp1 = svc.call(None);
+        let _rsp2 = svc.call(None);
+        assert_eq!(svc.load(), Cost(3.0));
+    }
+}
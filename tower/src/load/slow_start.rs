@@ -0,0 +1,275 @@
+//! A [`Load`] implementation that ramps up traffic to newly added services.
+
+#[cfg(feature = "discover")]
+use crate::discover::{Change, Discover};
+#[cfg(feature = "discover")]
+use futures_core::{ready, Stream};
+#[cfg(feature = "discover")]
+use pin_project_lite::pin_project;
+#[cfg(feature = "discover")]
+use std::pin::Pin;
+
+use super::Load;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower_service::Service;
+use tracing::trace;
+
+/// Scales the load of a newly added service so that it receives a gradually increasing share of
+/// requests.
+///
+/// A service that was just added to a balancer typically reports a low load: it has no pending
+/// requests, and latency-based estimators such as [`PeakEwma`] start out at a default value. If
+/// the balancer prefers it over the services that have been warmed up, it receives a flood of
+/// requests while its caches are still cold.
+///
+/// [`SlowStart`] wraps another [`Load`] implementation and reports its load divided by a _weight_
+/// that grows from `min_weight` to 1 over the slow start `window`, which starts when the
+/// [`SlowStart`] is created. After `window` has elapsed, the inner load is reported unchanged.
+///
+/// The weight grows as `(elapsed / window) ^ (1 / aggression)`, but never drops below
+/// `min_weight`. An `aggression` of 1.0 increases the weight linearly. Greater values increase the
+/// weight more quickly at the start of the window, and values between 0.0 and 1.0 increase it more
+/// slowly.
+///
+/// Since a zero load divided by any weight is still zero, the inner load is offset by one before
+/// it is scaled, and the [`Cost`] is computed as `(load + 1) / weight - 1`. This way, a new
+/// service without any pending requests still compares as more loaded than a warmed-up service
+/// without pending requests.
+///
+/// The inner load metric must be convertible into an [`f64`]; this is implemented for the metrics
+/// of [`PeakEwma`] and [`PendingRequests`].
+///
+/// [`PeakEwma`]: crate::load::PeakEwma
+/// [`PendingRequests`]: crate::load::PendingRequests
+#[derive(Debug)]
+pub struct SlowStart<S> {
+    service: S,
+    ramp: Ramp,
+    started_at: Instant,
+}
+
+#[cfg(feature = "discover")]
+pin_project! {
+    /// Wraps a `D`-typed stream of discovered services with [`SlowStart`].
+    ///
+    /// The slow start window of each service starts when it is discovered.
+    #[cfg_attr(docsrs, doc(cfg(feature = "discover")))]
+    #[derive(Debug)]
+    pub struct SlowStartDiscover<D> {
+        #[pin]
+        discover: D,
+        ramp: Ramp,
+    }
+}
+
+/// The slow start parameters, shared by all services of a [`SlowStartDiscover`].
+#[derive(Clone, Copy, Debug)]
+struct Ramp {
+    window: Duration,
+    aggression: f64,
+    min_weight: f64,
+}
+
+/// The load of a service, scaled by its slow start weight.
+#[derive(Copy, Clone, Debug, PartialEq, PartialOrd)]
+pub struct Cost(f64);
+
+// ===== impl SlowStart =====
+
+impl<S> SlowStart<S> {
+    /// Wraps an `S`-typed service so that its load is scaled up during the slow start `window`.
+    ///
+    /// # Panics
+    ///
+    /// If `aggression` is not positive, or if `min_weight` is not between 0.0 (exclusive) and 1.0.
+    pub fn new(service: S, window: Duration, aggression: f64, min_weight: f64) -> Self {
+        Self {
+            service,
+            ramp: Ramp::new(window, aggression, min_weight),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Returns the current slow start weight of the service, between `min_weight` and 1.0.
+    pub fn weight(&self) -> f64 {
+        self.ramp
+            .weight(Instant::now().saturating_duration_since(self.started_at))
+    }
+}
+
+impl<S> Load for SlowStart<S>
+where
+    S: Load,
+    S::Metric: Into<f64>,
+{
+    type Metric = Cost;
+
+    fn load(&self) -> Cost {
+        let load = self.service.load().into();
+        let weight = self.weight();
+        if weight >= 1.0 {
+            return Cost(load);
+        }
+
+        let cost = Cost((load + 1.0) / weight - 1.0);
+        trace!(load, weight, ?cost, "slow start");
+        cost
+    }
+}
+
+impl<S, Request> Service<Request> for SlowStart<S>
+where
+    S: Service<Request>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        self.service.call(req)
+    }
+}
+
+// ===== impl SlowStartDiscover =====
+
+#[cfg(feature = "discover")]
+impl<D> SlowStartDiscover<D> {
+    /// Wraps a `D`-typed [`Discover`] so that newly discovered services are slowly ramped up.
+    ///
+    /// See [`SlowStart::new`] for the meaning of the parameters.
+    ///
+    /// # Panics
+    ///
+    /// If `aggression` is not positive, or if `min_weight` is not between 0.0 (exclusive) and 1.0.
+    pub fn new(discover: D, window: Duration, aggression: f64, min_weight: f64) -> Self
+    where
+        D: Discover,
+    {
+        Self {
+            discover,
+            ramp: Ramp::new(window, aggression, min_weight),
+        }
+    }
+}
+
+#[cfg(feature = "discover")]
+impl<D> Stream for SlowStartDiscover<D>
+where
+    D: Discover,
+{
+    type Item = Result<Change<D::Key, SlowStart<D::Service>>, D::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+        let change = match ready!(this.discover.poll_discover(cx)).transpose()? {
+            None => return Poll::Ready(None),
+            Some(Change::Remove(k)) => Change::Remove(k),
+            Some(Change::Insert(k, svc)) => {
+                let slow_start = SlowStart {
+                    service: svc,
+                    ramp: *this.ramp,
+                    started_at: Instant::now(),
+                };
+                Change::Insert(k, slow_start)
+            }
+        };
+
+        Poll::Ready(Some(Ok(change)))
+    }
+}
+
+// ===== impl Ramp =====
+
+impl Ramp {
+    fn new(window: Duration, aggression: f64, min_weight: f64) -> Self {
+        assert!(aggression > 0.0, "aggression must be positive");
+        assert!(
+            0.0 < min_weight && min_weight <= 1.0,
+            "min_weight must be between 0.0 (exclusive) and 1.0"
+        );
+        Self {
+            window,
+            aggression,
+            min_weight,
+        }
+    }
+
+    fn weight(&self, elapsed: Duration) -> f64 {
+        if elapsed >= self.window {
+            return 1.0;
+        }
+
+        let progress = elapsed.as_secs_f64() / self.window.as_secs_f64();
+        progress.powf(1.0 / self.aggression).max(self.min_weight)
+    }
+}
+
+// ===== impl Cost =====
+
+impl From<Cost> for f64 {
+    fn from(Cost(cost): Cost) -> f64 {
+        cost
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::load::Constant;
+    use tokio::time;
+
+    fn slow_start(load: f64, aggression: f64) -> SlowStart<Constant<(), f64>> {
+        SlowStart::new(
+            Constant::new((), load),
+            Duration::from_secs(10),
+            aggression,
+            0.1,
+        )
+    }
+
+    #[tokio::test]
+    async fn linear() {
+        time::pause();
+
+        let svc = slow_start(1.0, 1.0);
+        assert_eq!(svc.weight(), 0.1);
+        assert_eq!(svc.load(), Cost(19.0));
+
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(svc.weight(), 0.5);
+        assert_eq!(svc.load(), Cost(3.0));
+
+        time::advance(Duration::from_secs(5)).await;
+        assert_eq!(svc.weight(), 1.0);
+        assert_eq!(svc.load(), Cost(1.0));
+    }
+
+    #[tokio::test]
+    async fn aggressive() {
+        time::pause();
+
+        let svc = slow_start(0.0, 2.0);
+        time::advance(Duration::from_millis(2_500)).await;
+        assert_eq!(svc.weight(), 0.5);
+        assert_eq!(svc.load(), Cost(1.0));
+
+        time::advance(Duration::from_millis(7_500)).await;
+        assert_eq!(svc.load(), Cost(0.0));
+    }
+
+    #[tokio::test]
+    async fn new_service_is_more_loaded() {
+        time::pause();
+
+        let warm = slow_start(0.0, 1.0);
+        time::advance(Duration::from_secs(10)).await;
+        let cold = slow_start(0.0, 1.0);
+        assert!(warm.load() < cold.load());
+    }
+}
@@ -0,0 +1,61 @@
+use pin_project_lite::pin_project;
+use std::{
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+pin_project! {
+    /// Future that resolves to the response, or to the failure to make a member of the pool.
+    #[derive(Debug)]
+    pub struct ResponseFuture<F> {
+        #[pin]
+        inner: Inner<F>,
+    }
+}
+
+pin_project! {
+    #[project = InnerProj]
+    #[derive(Debug)]
+    enum Inner<F> {
+        Future {
+            #[pin]
+            fut: F,
+        },
+        Error {
+            error: Option<crate::BoxError>,
+        },
+    }
+}
+
+impl<F> ResponseFuture<F> {
+    pub(crate) fn new(fut: F) -> Self {
+        ResponseFuture {
+            inner: Inner::Future { fut },
+        }
+    }
+
+    pub(crate) fn error(error: crate::BoxError) -> Self {
+        ResponseFuture {
+            inner: Inner::Error { error: Some(error) },
+        }
+    }
+}
+
+impl<F, T, E> Future for ResponseFuture<F>
+where
+    F: Future<Output = Result<T, E>>,
+    E: Into<crate::BoxError>,
+{
+    type Output = Result<T, crate::BoxError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.project().inner.project() {
+            InnerProj::Future { fut } => fut.poll(cx).map_err(Into::into),
+            InnerProj::Error { error } => {
+                let error = error.take().expect("polled after ready");
+                Poll::Ready(Err(error))
+            }
+        }
+    }
+}
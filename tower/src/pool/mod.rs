@@ -0,0 +1,52 @@
+//! A pool of interchangeable services to a single target.
+//!
+//! [`Reconnect`] maintains a single connection to a target, and the [balancers] spread requests
+//! across many endpoints. A [`Pool`] sits in between: it makes a number of services for a single
+//! target with a [`MakeService`], such as a [`MakeConnection`], and dispatches each request to
+//! one of them that is ready. This is the classic connection pool, for protocols where a
+//! connection can only serve a limited number of requests at once.
+//!
+//! The pool keeps between a minimum and a maximum number of members. A new member is only made
+//! when no member is ready, or when the pool is below its minimum size, and if making one fails,
+//! another one isn't made until the pool is polled again. If the pool has no members, the pool
+//! becomes ready instead, and the failure is returned from the next request, as with
+//! [`Reconnect`]. Members that fail are dropped, and are replaced as needed, and members that
+//! haven't been used for a while can be dropped with an idle timeout.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # #[cfg(feature = "util")]
+//! # async fn example() -> Result<(), tower::BoxError> {
+//! use std::convert::Infallible;
+//! use std::time::Duration;
+//! use tower::pool::Pool;
+//! use tower::{service_fn, ServiceExt};
+//!
+//! // Makes a new "connection" to the target.
+//! let connect = service_fn(|target: &'static str| async move {
+//!     let conn = service_fn(move |req: String| async move {
+//!         Ok::<_, Infallible>(format!("{} replied to {}", target, req))
+//!     });
+//!     Ok::<_, Infallible>(conn)
+//! });
+//!
+//! let pool = Pool::new(connect, "backend")
+//!     .with_size(1, 8)
+//!     .with_idle_timeout(Duration::from_secs(60));
+//! let rsp = pool.oneshot("hello".to_string()).await?;
+//! assert_eq!(rsp, "backend replied to hello");
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! [`Reconnect`]: crate::reconnect::Reconnect
+//! [balancers]: crate::balance
+//! [`MakeService`]: crate::MakeService
+//! [`MakeConnection`]: crate::make::MakeConnection
+
+mod future;
+mod service;
+
+pub use self::future::ResponseFuture;
+pub use self::service::{Pool, Stats};
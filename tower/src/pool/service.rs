@@ -0,0 +1,384 @@
+use super::future::ResponseFuture;
+use crate::make::MakeService;
+use crate::ready_cache::{error::Failed, ReadyCache};
+use std::collections::HashMap;
+use std::{
+    fmt,
+    future::Future,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::time::Instant;
+use tower_service::Service;
+use tracing::{debug, trace};
+
+/// Dispatches requests to a pool of services made for a single target.
+///
+/// See the [module-level documentation](..) for details.
+pub struct Pool<M, Target, Req>
+where
+    M: MakeService<Target, Req>,
+{
+    make: M,
+    target: Target,
+    members: ReadyCache<usize, M::Service, Req>,
+    // The member that is being made, if any.
+    making: Option<Pin<Box<M::Future>>>,
+    next_id: usize,
+    // When each member was last used. Unlike `members`, this only has entries for members that
+    // haven't been dropped, so it's also used to count them.
+    used: HashMap<usize, Instant>,
+    ready_index: Option<usize>,
+    // The failure to make a member while the pool was empty, which is returned from the next call.
+    error: Option<crate::BoxError>,
+    min: usize,
+    max: usize,
+    idle_timeout: Option<Duration>,
+    created: u64,
+    failed: u64,
+    evicted: u64,
+}
+
+/// What became of the member that is being made, when it was polled.
+enum Made {
+    Member,
+    Failed,
+    // No member was made yet, or none is being made.
+    Nothing,
+}
+
+/// A point-in-time view of a [`Pool`].
+///
+/// Returned by [`Pool::stats`].
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    ready: usize,
+    pending: usize,
+    making: usize,
+    created: u64,
+    failed: u64,
+    evicted: u64,
+}
+
+impl<M, Target, Req> Pool<M, Target, Req>
+where
+    M: MakeService<Target, Req>,
+{
+    /// Creates a pool of services made for `target` by `make`.
+    ///
+    /// By default, the pool holds up to 10 members, is initially empty, and keeps members until
+    /// they fail.
+    pub fn new(make: M, target: Target) -> Self {
+        Self {
+            make,
+            target,
+            members: ReadyCache::default(),
+            making: None,
+            next_id: 0,
+            used: HashMap::new(),
+            ready_index: None,
+            error: None,
+            min: 0,
+            max: 10,
+            idle_timeout: None,
+            created: 0,
+            failed: 0,
+            evicted: 0,
+        }
+    }
+
+    /// Sets the minimum and maximum number of members.
+    ///
+    /// The pool makes members until it has at least `min`, and it doesn't drop idle members
+    /// below `min`.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is zero, or if `min` is greater than `max`.
+    pub fn with_size(mut self, min: usize, max: usize) -> Self {
+        assert!(max > 0, "max must be positive");
+        assert!(min <= max, "min must not be greater than max");
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    /// Drops members that haven't been called for `timeout`, whether they are ready or not, as
+    /// long as the pool has more than its minimum number of members.
+    ///
+    /// Idle members are only dropped when the pool is polled for readiness.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Returns the number of members in the pool, not counting one that is being made.
+    pub fn len(&self) -> usize {
+        self.used.len()
+    }
+
+    /// Returns whether the pool has no members.
+    pub fn is_empty(&self) -> bool {
+        self.used.is_empty()
+    }
+
+    /// Returns the current size of the pool, and counts of what has happened to its members.
+    pub fn stats(&self) -> Stats {
+        let ready = self.members.ready_len();
+        Stats {
+            ready,
+            pending: self.len() - ready,
+            making: self.making.is_some() as usize,
+            created: self.created,
+            failed: self.failed,
+            evicted: self.evicted,
+        }
+    }
+}
+
+impl<M, Target, Req> Pool<M, Target, Req>
+where
+    M: MakeService<Target, Req>,
+    M::MakeError: Into<crate::BoxError>,
+    M::Error: Into<crate::BoxError>,
+    Target: Clone,
+{
+    /// Drives pending members to readiness, dropping members that fail.
+    fn promote_pending(&mut self, cx: &mut Context<'_>) {
+        loop {
+            match self.members.poll_pending(cx) {
+                Poll::Ready(Ok(())) | Poll::Pending => return,
+                Poll::Ready(Err(Failed(id, error))) => {
+                    debug!(%error, "dropping failed member");
+                    self.forget(id);
+                }
+            }
+        }
+    }
+
+    /// Drops members that have been idle for too long, down to the minimum size.
+    fn evict_idle(&mut self) {
+        let timeout = match self.idle_timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+        let now = Instant::now();
+        let idle = self
+            .members
+            .iter()
+            .map(|(id, _, _)| *id)
+            .filter(|id| {
+                self.used
+                    .get(id)
+                    .map_or(false, |used| now - *used >= timeout)
+            })
+            .collect::<Vec<_>>();
+        for id in idle {
+            if self.len() <= self.min {
+                break;
+            }
+            trace!(id, "dropping idle member");
+            self.members.evict(&id);
+            self.used.remove(&id);
+            self.evicted += 1;
+        }
+    }
+
+    /// Polls the member that is being made, adding it to the pool once it's made.
+    fn poll_making(&mut self, cx: &mut Context<'_>) -> Made {
+        let making = match self.making.as_mut() {
+            Some(making) => making,
+            None => return Made::Nothing,
+        };
+        match making.as_mut().poll(cx) {
+            Poll::Pending => Made::Nothing,
+            Poll::Ready(Ok(member)) => {
+                self.making = None;
+                let id = self.next_id;
+                self.next_id += 1;
+                trace!(id, "made member");
+                self.members.push(id, member);
+                self.used.insert(id, Instant::now());
+                self.created += 1;
+                Made::Member
+            }
+            Poll::Ready(Err(error)) => {
+                self.making = None;
+                let error = error.into();
+                debug!(%error, "failed to make member");
+                if self.is_empty() {
+                    self.error = Some(error);
+                }
+                Made::Failed
+            }
+        }
+    }
+
+    /// Starts making a new member, if none is being made and the pool has room for it.
+    ///
+    /// Returns whether a member is now being made.
+    fn make_member(&mut self, cx: &mut Context<'_>) -> Result<bool, crate::BoxError> {
+        if self.making.is_some() || self.len() >= self.max {
+            return Ok(false);
+        }
+        match self.make.poll_ready(cx) {
+            Poll::Ready(Ok(())) => {
+                trace!(len = self.len(), "making member");
+                let making = self.make.make_service(self.target.clone());
+                self.making = Some(Box::pin(making));
+                Ok(true)
+            }
+            Poll::Ready(Err(error)) => Err(error.into()),
+            Poll::Pending => Ok(false),
+        }
+    }
+
+    /// Finds a member that is ready, dropping members that fail.
+    fn ready_member(&mut self, cx: &mut Context<'_>) -> Option<usize> {
+        // Members that are no longer ready are moved back to the pending set, and members that
+        // fail are dropped, so the first index is checked until a ready member is found.
+        while let Some((id, _)) = self.members.get_ready_index(0) {
+            let id = *id;
+            match self.members.check_ready_index(cx, 0) {
+                Ok(true) => return Some(0),
+                Ok(false) => {}
+                Err(Failed(_, error)) => {
+                    debug!(%error, "dropping failed member");
+                    self.forget(id);
+                }
+            }
+        }
+        None
+    }
+
+    /// Forgets a member that failed.
+    fn forget(&mut self, id: usize) {
+        self.used.remove(&id);
+        self.failed += 1;
+    }
+}
+
+impl<M, Target, Req> Service<Req> for Pool<M, Target, Req>
+where
+    M: MakeService<Target, Req>,
+    M::MakeError: Into<crate::BoxError>,
+    M::Error: Into<crate::BoxError>,
+    Target: Clone,
+{
+    type Response = M::Response;
+    type Error = crate::BoxError;
+    type Future = ResponseFuture<<M::Service as Service<Req>>::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        // Indexes may have been perturbed since a prior invocation.
+        self.ready_index = None;
+        // Whether making a member failed. Another one isn't made until the pool is polled again,
+        // so that a `MakeService` that fails right away isn't retried in a busy loop.
+        let mut failed = false;
+        loop {
+            self.promote_pending(cx);
+            self.evict_idle();
+            match self.poll_making(cx) {
+                Made::Member => continue,
+                Made::Failed => failed = true,
+                Made::Nothing => {}
+            }
+
+            if let Some(index) = self.ready_member(cx) {
+                self.ready_index = Some(index);
+                // A member was made since the failure, so the request goes to it instead.
+                self.error = None;
+                if self.len() < self.min && !failed {
+                    self.make_member(cx)?;
+                    // The new member is polled the next time the pool is polled.
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            // There are no members to send the next request to, so it gets the failure instead.
+            if self.error.is_some() {
+                return Poll::Ready(Ok(()));
+            }
+
+            // No member is ready, so make another one if there's room. Otherwise, the task is
+            // woken once a member is ready.
+            if failed || !self.make_member(cx)? {
+                trace!(stats = ?self.stats(), "no member ready");
+                return Poll::Pending;
+            }
+        }
+    }
+
+    fn call(&mut self, request: Req) -> Self::Future {
+        if let Some(error) = self.error.take() {
+            return ResponseFuture::error(error);
+        }
+        let index = self.ready_index.take().expect("called before ready");
+        if let Some((id, _)) = self.members.get_ready_index(index) {
+            self.used.insert(*id, Instant::now());
+        }
+        ResponseFuture::new(self.members.call_ready_index(index, request))
+    }
+}
+
+impl<M, Target, Req> fmt::Debug for Pool<M, Target, Req>
+where
+    M: MakeService<Target, Req> + fmt::Debug,
+    Target: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Pool")
+            .field("make", &self.make)
+            .field("target", &self.target)
+            .field("min", &self.min)
+            .field("max", &self.max)
+            .field("idle_timeout", &self.idle_timeout)
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+// ===== impl Stats =====
+
+impl Stats {
+    /// Returns the number of members in the pool.
+    pub fn len(&self) -> usize {
+        self.ready + self.pending
+    }
+
+    /// Returns whether the pool has no members.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of members that were ready when they were last polled.
+    pub fn ready(&self) -> usize {
+        self.ready
+    }
+
+    /// Returns the number of members that are being driven to readiness.
+    pub fn pending(&self) -> usize {
+        self.pending
+    }
+
+    /// Returns the number of members that are being made.
+    pub fn making(&self) -> usize {
+        self.making
+    }
+
+    /// Returns the number of members that have been made.
+    pub fn created(&self) -> u64 {
+        self.created
+    }
+
+    /// Returns the number of members that have been dropped because they failed.
+    pub fn failed(&self) -> u64 {
+        self.failed
+    }
+
+    /// Returns the number of members that have been dropped because they were idle.
+    pub fn evicted(&self) -> u64 {
+        self.evicted
+    }
+}
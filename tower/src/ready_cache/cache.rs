@@ -12,6 +12,8 @@ use std::pin::Pin;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::{sleep, Instant, Sleep};
 use tower_service::Service;
 use tracing::{debug, trace};
 
@@ -55,6 +57,13 @@ use tracing::{debug, trace};
 /// service. In such a case, it should be noted that calls to
 /// [`ReadyCache::poll_pending`] and [`ReadyCache::evict`] may perturb the order of
 /// the ready set, so any cached indexes should be discarded after such a call.
+///
+/// By default, the cache holds any number of services, for as long as they
+/// remain ready. [`ReadyCache::with_max_len`] limits the number of services,
+/// evicting the least recently used service to make room for a new one, and
+/// [`ReadyCache::with_idle_timeout`] drops ready services that haven't been
+/// used for a while. With these, a `ReadyCache` can serve as a cache of
+/// per-key connections.
 pub struct ReadyCache<K, S, Req>
 where
     K: Eq + Hash,
@@ -69,8 +78,15 @@ where
     ///
     /// The cancelation oneshot is preserved (though unused) while the service is
     /// ready so that it need not be reallocated each time a request is
-    /// dispatched.
-    ready: IndexMap<K, (S, CancelPair)>,
+    /// dispatched. Each service is stored along with when it was last used.
+    ready: IndexMap<K, (S, CancelPair, Instant)>,
+
+    /// The maximum number of services in the cache, if limited.
+    max_len: Option<usize>,
+    /// How long a ready service may go unused before it is dropped, if limited.
+    idle_timeout: Option<Duration>,
+    /// How long a service may be pending before it fails, if limited.
+    pending_timeout: Option<Duration>,
 }
 
 // Safety: This is safe because we do not use `Pin::new_unchecked`.
@@ -94,20 +110,44 @@ type CancelPair = (CancelTx, CancelRx);
 enum PendingError<K, E> {
     Canceled(K),
     Inner(K, E),
+    TimedOut(K),
 }
 
 pin_project_lite::pin_project! {
     /// A [`Future`] that becomes satisfied when an `S`-typed service is ready.
     ///
-    /// May fail due to cancelation, i.e. if the service is evicted from the balancer.
+    /// May fail due to cancelation, i.e. if the service is evicted from the balancer,
+    /// or if the service does not become ready before its deadline.
     struct Pending<K, S, Req> {
         key: Option<K>,
         cancel: Option<CancelRx>,
         ready: Option<S>,
+        used: Instant,
+        deadline: Option<Pin<Box<Sleep>>>,
         _pd: std::marker::PhantomData<Req>,
     }
 }
 
+/// The set that a service in a [`ReadyCache`] is in.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum State {
+    /// The service has previously become ready.
+    Ready,
+    /// The service is being driven to readiness.
+    Pending,
+}
+
+/// A point-in-time view of a service in a [`ReadyCache`].
+///
+/// Returned by [`ReadyCache::snapshot`].
+#[cfg(feature = "load")]
+#[derive(Clone, Debug)]
+pub struct Snapshot<K, M> {
+    key: K,
+    state: State,
+    load: M,
+}
+
 // === ReadyCache ===
 
 impl<K, S, Req> Default for ReadyCache<K, S, Req>
@@ -120,6 +160,9 @@ where
             ready: IndexMap::default(),
             pending: FuturesUnordered::new(),
             pending_cancel_txs: IndexMap::default(),
+            max_len: None,
+            idle_timeout: None,
+            pending_timeout: None,
         }
     }
 }
@@ -134,11 +177,17 @@ where
             pending,
             pending_cancel_txs,
             ready,
+            max_len,
+            idle_timeout,
+            pending_timeout,
         } = self;
         f.debug_struct("ReadyCache")
             .field("pending", pending)
             .field("pending_cancel_txs", pending_cancel_txs)
             .field("ready", ready)
+            .field("max_len", max_len)
+            .field("idle_timeout", idle_timeout)
+            .field("pending_timeout", pending_timeout)
             .finish()
     }
 }
@@ -147,6 +196,47 @@ impl<K, S, Req> ReadyCache<K, S, Req>
 where
     K: Eq + Hash,
 {
+    /// Limits the cache to `max` services.
+    ///
+    /// When a service is pushed with a key that isn't in a full cache, the
+    /// least recently used service is evicted to make room for it. A service is
+    /// used when it is pushed or called.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is zero.
+    pub fn with_max_len(mut self, max: usize) -> Self {
+        assert!(max > 0, "max_len must be positive");
+        self.max_len = Some(max);
+        self
+    }
+
+    /// Drops ready services that haven't been used for `timeout`.
+    ///
+    /// A service is used when it is pushed or called. Idle services are only
+    /// dropped when [`ReadyCache::poll_pending`] is invoked.
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Fails services that don't become ready within `timeout` of being pushed.
+    ///
+    /// The timeout only applies until a service first becomes ready; a service
+    /// that is pending again after it is called, or that is no longer ready
+    /// when it is checked, can stay pending for as long as it needs to. A
+    /// service that doesn't become ready in time is evicted, and [`ReadyCache::poll_pending`] returns a [`Failed`] error
+    /// with a [`PendingTimeout`] for it.
+    ///
+    /// Requires a Tokio runtime with the time driver enabled.
+    ///
+    /// [`Failed`]: crate::ready_cache::error::Failed
+    /// [`PendingTimeout`]: crate::ready_cache::error::PendingTimeout
+    pub fn with_pending_timeout(mut self, timeout: Duration) -> Self {
+        self.pending_timeout = Some(timeout);
+        self
+    }
+
     /// Returns the total number of services in the cache.
     pub fn len(&self) -> usize {
         self.ready_len() + self.pending_len()
@@ -207,6 +297,37 @@ where
         self.ready.iter_mut().map(|(k, s)| (k, &mut s.0))
     }
 
+    /// Returns an iterator over the pending keys and services.
+    ///
+    /// Services that have been evicted or replaced, but not yet dropped by
+    /// [`ReadyCache::poll_pending`], are skipped.
+    pub fn iter_pending(&self) -> impl Iterator<Item = (&K, &S)> {
+        self.live_pending()
+            .filter_map(|pending| Some((pending.key.as_ref()?, pending.ready.as_ref()?)))
+    }
+
+    /// Returns an iterator over the pending services that have not been
+    /// evicted or replaced.
+    fn live_pending(&self) -> impl Iterator<Item = &Pending<K, S, Req>> {
+        self.pending
+            .iter()
+            .filter(|pending| match pending.cancel.as_ref() {
+                Some(CancelRx(cancel)) => !cancel.canceled.load(Ordering::SeqCst),
+                None => false,
+            })
+    }
+
+    /// Returns an iterator over all keys and services in the cache, along with
+    /// the set that each service is in.
+    ///
+    /// Ready services are yielded before pending services. A key may be
+    /// yielded twice if a ready service is being replaced by a pending one.
+    pub fn iter(&self) -> impl Iterator<Item = (&K, &S, State)> {
+        let ready = self.iter_ready().map(|(k, s)| (k, s, State::Ready));
+        let pending = self.iter_pending().map(|(k, s)| (k, s, State::Pending));
+        ready.chain(pending)
+    }
+
     /// Evicts an item from the cache.
     ///
     /// Returns true if a service was marked for eviction.
@@ -229,6 +350,26 @@ where
     }
 }
 
+#[cfg(feature = "load")]
+impl<K, S, Req> ReadyCache<K, S, Req>
+where
+    K: Clone + Eq + Hash,
+    S: crate::load::Load,
+{
+    /// Returns the key, state, and current load of every service in the cache.
+    ///
+    /// Ready services are listed before pending services.
+    pub fn snapshot(&self) -> Vec<Snapshot<K, S::Metric>> {
+        self.iter()
+            .map(|(key, svc, state)| Snapshot {
+                key: key.clone(),
+                state,
+                load: svc.load(),
+            })
+            .collect()
+    }
+}
+
 impl<K, S, Req> ReadyCache<K, S, Req>
 where
     K: Clone + Eq + Hash,
@@ -245,13 +386,70 @@ where
     /// the pending set; OR, when the new service becomes ready, it will replace
     /// the prior service in the ready set.
     ///
+    /// If the cache is limited by [`with_max_len`] and is full, the least
+    /// recently used service is evicted first, unless a service with the same
+    /// key is already in the cache.
+    ///
     /// [`poll_pending`]: crate::ready_cache::cache::ReadyCache::poll_pending
+    /// [`with_max_len`]: crate::ready_cache::cache::ReadyCache::with_max_len
     pub fn push(&mut self, key: K, svc: S) {
+        if let Some(max) = self.max_len {
+            if !self.contains(&key) {
+                while self.distinct_len() >= max {
+                    match self.least_recently_used() {
+                        Some(lru) => {
+                            debug!("evicting least recently used endpoint");
+                            self.evict(&lru);
+                        }
+                        None => break,
+                    }
+                }
+            }
+        }
+
         let cancel = cancelable();
-        self.push_pending(key, svc, cancel);
+        let deadline = self.pending_timeout.map(|timeout| Box::pin(sleep(timeout)));
+        self.push_pending(key, svc, cancel, Instant::now(), deadline);
     }
 
-    fn push_pending(&mut self, key: K, svc: S, (cancel_tx, cancel_rx): CancelPair) {
+    /// Returns whether a service with the given key is in either set.
+    fn contains(&self, key: &K) -> bool {
+        self.ready.contains_key(key) || self.pending_cancel_txs.contains_key(key)
+    }
+
+    /// Returns the number of distinct keys in the cache.
+    ///
+    /// Unlike [`ReadyCache::len`], this counts a ready service that is being
+    /// replaced by a pending one once.
+    fn distinct_len(&self) -> usize {
+        let replacing = self
+            .pending_cancel_txs
+            .keys()
+            .filter(|key| self.ready.contains_key(*key))
+            .count();
+        self.ready.len() + self.pending_cancel_txs.len() - replacing
+    }
+
+    /// Returns the key of the service that was used least recently.
+    fn least_recently_used(&self) -> Option<K> {
+        let ready = self.ready.iter().map(|(key, (_, _, used))| (key, *used));
+        let pending = self
+            .live_pending()
+            .filter_map(|pending| Some((pending.key.as_ref()?, pending.used)));
+        ready
+            .chain(pending)
+            .min_by_key(|(_, used)| *used)
+            .map(|(key, _)| key.clone())
+    }
+
+    fn push_pending(
+        &mut self,
+        key: K,
+        svc: S,
+        (cancel_tx, cancel_rx): CancelPair,
+        used: Instant,
+        deadline: Option<Pin<Box<Sleep>>>,
+    ) {
         if let Some(c) = self.pending_cancel_txs.insert(key.clone(), cancel_tx) {
             // If there is already a service for this key, cancel it.
             c.cancel();
@@ -260,6 +458,8 @@ where
             key: Some(key),
             cancel: Some(cancel_rx),
             ready: Some(svc),
+            used,
+            deadline,
             _pd: std::marker::PhantomData,
         });
     }
@@ -279,17 +479,28 @@ where
     /// [`push`]: crate::ready_cache::cache::ReadyCache::push
     /// [`call_ready_index`]: crate::ready_cache::cache::ReadyCache::call_ready_index
     pub fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), error::Failed<K>>> {
+        if let Some(timeout) = self.idle_timeout {
+            let now = Instant::now();
+            self.ready.retain(|_, (_, _, used)| {
+                let idle = now.saturating_duration_since(*used) >= timeout;
+                if idle {
+                    debug!("dropping idle endpoint");
+                }
+                !idle
+            });
+        }
+
         loop {
             match Pin::new(&mut self.pending).poll_next(cx) {
                 Poll::Pending => return Poll::Pending,
                 Poll::Ready(None) => return Poll::Ready(Ok(())),
-                Poll::Ready(Some(Ok((key, svc, cancel_rx)))) => {
+                Poll::Ready(Some(Ok((key, svc, cancel_rx, used)))) => {
                     trace!("endpoint ready");
                     let cancel_tx = self.pending_cancel_txs.swap_remove(&key);
                     if let Some(cancel_tx) = cancel_tx {
                         // Keep track of the cancelation so that it need not be
                         // recreated after the service is used.
-                        self.ready.insert(key, (svc, (cancel_tx, cancel_rx)));
+                        self.ready.insert(key, (svc, (cancel_tx, cancel_rx), used));
                     } else {
                         assert!(
                             cancel_tx.is_some(),
@@ -310,6 +521,15 @@ where
                     );
                     return Err(error::Failed(key, e.into())).into();
                 }
+                Poll::Ready(Some(Err(PendingError::TimedOut(key)))) => {
+                    let cancel_tx = self.pending_cancel_txs.swap_remove(&key);
+                    assert!(
+                        cancel_tx.is_some(),
+                        "services that time out must have a pending cancelation"
+                    );
+                    let error = error::PendingTimeout::new();
+                    return Err(error::Failed(key, error.into())).into();
+                }
             }
         }
     }
@@ -342,13 +562,13 @@ where
     ) -> Result<bool, error::Failed<K>> {
         let svc = match self.ready.get_index_mut(index) {
             None => return Ok(false),
-            Some((_, (svc, _))) => svc,
+            Some((_, (svc, _, _))) => svc,
         };
         match svc.poll_ready(cx) {
             Poll::Ready(Ok(())) => Ok(true),
             Poll::Pending => {
                 // became unready; so move it back there.
-                let (key, (svc, cancel)) = self
+                let (key, (svc, cancel, used)) = self
                     .ready
                     .swap_remove_index(index)
                     .expect("invalid ready index");
@@ -356,7 +576,7 @@ where
                 // If a new version of this service has been added to the
                 // unready set, don't overwrite it.
                 if !self.pending_contains(&key) {
-                    self.push_pending(key, svc, cancel);
+                    self.push_pending(key, svc, cancel, used, None);
                 }
 
                 Ok(false)
@@ -391,7 +611,7 @@ where
     ///
     /// If the specified index is out of range.
     pub fn call_ready_index(&mut self, index: usize, req: Req) -> S::Future {
-        let (key, (mut svc, cancel)) = self
+        let (key, (mut svc, cancel, _)) = self
             .ready
             .swap_remove_index(index)
             .expect("check_ready_index was not called");
@@ -401,13 +621,33 @@ where
         // If a new version of this service has been added to the
         // unready set, don't overwrite it.
         if !self.pending_contains(&key) {
-            self.push_pending(key, svc, cancel);
+            self.push_pending(key, svc, cancel, Instant::now(), None);
         }
 
         fut
     }
 }
 
+// === impl Snapshot ===
+
+#[cfg(feature = "load")]
+impl<K, M> Snapshot<K, M> {
+    /// Returns the service's key.
+    pub fn key(&self) -> &K {
+        &self.key
+    }
+
+    /// Returns whether the service was ready or pending.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the service's load.
+    pub fn load(&self) -> &M {
+        &self.load
+    }
+}
+
 // === impl Cancel ===
 
 /// Creates a cancelation sender and receiver.
@@ -436,7 +676,7 @@ impl<K, S, Req> Future for Pending<K, S, Req>
 where
     S: Service<Req>,
 {
-    type Output = Result<(K, S, CancelRx), PendingError<K, S::Error>>;
+    type Output = Result<(K, S, CancelRx, Instant), PendingError<K, S::Error>>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.project();
@@ -467,12 +707,20 @@ where
                     !cancel.canceled.load(Ordering::SeqCst),
                     "cancelation cannot be notified while polling a pending service"
                 );
+                // Fail the service if it has been pending for too long.
+                if let Some(deadline) = this.deadline.as_mut() {
+                    if deadline.as_mut().poll(cx).is_ready() {
+                        let key = this.key.take().expect("polled after complete");
+                        return Err(PendingError::TimedOut(key)).into();
+                    }
+                }
                 Poll::Pending
             }
             Poll::Ready(Ok(())) => {
                 let key = this.key.take().expect("polled after complete");
                 let cancel = this.cancel.take().expect("polled after complete");
-                Ok((key, this.ready.take().expect("polled after ready"), cancel)).into()
+                let svc = this.ready.take().expect("polled after ready");
+                Ok((key, svc, cancel, *this.used)).into()
             }
             Poll::Ready(Err(e)) => {
                 let key = this.key.take().expect("polled after compete");
@@ -492,12 +740,16 @@ where
             key,
             cancel,
             ready,
+            used,
+            deadline,
             _pd,
         } = self;
         f.debug_struct("Pending")
             .field("key", key)
             .field("cancel", cancel)
             .field("ready", ready)
+            .field("used", used)
+            .field("deadline", deadline)
             .finish()
     }
 }
@@ -4,6 +4,15 @@
 /// error.
 pub struct Failed<K>(pub K, pub crate::BoxError);
 
+/// An error indicating that a service did not become ready before its pending
+/// timeout elapsed.
+///
+/// See [`ReadyCache::with_pending_timeout`].
+///
+/// [`ReadyCache::with_pending_timeout`]: crate::ready_cache::ReadyCache::with_pending_timeout
+#[derive(Debug, Default)]
+pub struct PendingTimeout(());
+
 // === Failed ===
 
 impl<K: std::fmt::Debug> std::fmt::Debug for Failed<K> {
@@ -26,3 +35,20 @@ impl<K: std::fmt::Debug> std::error::Error for Failed<K> {
         Some(&*self.1)
     }
 }
+
+// === PendingTimeout ===
+
+impl PendingTimeout {
+    /// Constructs a new pending timeout error.
+    pub const fn new() -> Self {
+        PendingTimeout(())
+    }
+}
+
+impl std::fmt::Display for PendingTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.pad("service did not become ready in time")
+    }
+}
+
+impl std::error::Error for PendingTimeout {}
@@ -0,0 +1,35 @@
+//! Error types
+
+use std::fmt;
+
+/// An error returned by [`Reconnect`] once it has given up connecting, after
+/// the maximum number of consecutive failed connection attempts.
+///
+/// [`Reconnect`]: crate::reconnect::Reconnect
+#[derive(Debug)]
+pub struct Exhausted {
+    attempts: usize,
+}
+
+impl Exhausted {
+    pub(crate) const fn new(attempts: usize) -> Self {
+        Exhausted { attempts }
+    }
+
+    /// Returns the number of failed connection attempts.
+    pub fn attempts(&self) -> usize {
+        self.attempts
+    }
+}
+
+impl fmt::Display for Exhausted {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "gave up connecting after {} failed attempts",
+            self.attempts
+        )
+    }
+}
+
+impl std::error::Error for Exhausted {}
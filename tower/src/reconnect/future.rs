@@ -23,17 +23,25 @@ pin_project! {
             fut: F,
         },
         Error {
-            error: Option<E>,
+            error: Option<Failure<E>>,
         },
     }
 }
 
+/// A failure to connect, which is returned by the next call.
+#[derive(Debug)]
+pub(crate) enum Failure<E> {
+    Make(E),
+    // The error was already converted to describe it.
+    Boxed(crate::BoxError),
+}
+
 impl<F, E> Inner<F, E> {
     fn future(fut: F) -> Self {
         Self::Future { fut }
     }
 
-    fn error(error: Option<E>) -> Self {
+    fn error(error: Option<Failure<E>>) -> Self {
         Self::Error { error }
     }
 }
@@ -45,7 +53,7 @@ impl<F, E> ResponseFuture<F, E> {
         }
     }
 
-    pub(crate) fn error(error: E) -> Self {
+    pub(crate) fn error(error: Failure<E>) -> Self {
         ResponseFuture {
             inner: Inner::error(Some(error)),
         }
@@ -65,7 +73,10 @@ where
         match me.inner.project() {
             InnerProj::Future { fut } => fut.poll(cx).map_err(Into::into),
             InnerProj::Error { error } => {
-                let e = error.take().expect("Polled after ready.").into();
+                let e = match error.take().expect("Polled after ready.") {
+                    Failure::Make(e) => e.into(),
+                    Failure::Boxed(e) => e,
+                };
                 Poll::Ready(Err(e))
             }
         }
@@ -9,39 +9,133 @@
 //! call the service again even if the inner `MakeService` was unable to
 //! connect on the last call.
 //!
+//! By default, a failed connection attempt is retried as soon as the
+//! `Reconnect` service is polled again. [`Reconnect::with_backoff`] spaces out
+//! consecutive failed attempts, and [`Reconnect::with_max_attempts`] gives up
+//! after a number of them.
+//!
+//! Connections can also be recycled, so that load is spread again after
+//! backends scale out, with [`Reconnect::with_max_age`] and
+//! [`Reconnect::with_idle_timeout`].
+//!
+//! The state of the connection can be observed through a [`watch`] channel,
+//! returned by [`Reconnect::watch_state`].
+//!
 //! [`MakeService`]: crate::make::MakeService
 //! [`Service`]: crate::Service
 
+pub mod error;
 mod future;
 
 pub use future::ResponseFuture;
 
 use crate::make::MakeService;
+use crate::retry::backoff::{Backoff, MakeBackoff};
+use crate::util::rng::{HasherRng, Rng};
+use future::Failure;
 use std::fmt;
 use std::{
     future::Future,
     pin::Pin,
     task::{Context, Poll},
+    time::Duration,
 };
+use tokio::sync::watch;
+use tokio::time::Instant;
 use tower_service::Service;
-use tracing::trace;
+use tracing::{debug, trace};
 
 /// Reconnect to failed services.
-pub struct Reconnect<M, Target>
+pub struct Reconnect<M, Target, B = NoBackoff>
 where
     M: Service<Target>,
+    B: MakeBackoff,
 {
     mk_service: M,
-    state: State<M::Future, M::Response>,
+    state: State<M::Future, M::Response, Pin<Box<BackoffFuture<B>>>>,
     target: Target,
-    error: Option<M::Error>,
+    error: Option<Failure<M::Error>>,
+    make_backoff: B,
+    // The backoff session for the current run of failed connection attempts.
+    backoff: Option<B::Backoff>,
+    attempts: usize,
+    max_attempts: Option<usize>,
+    lifetime: Option<Lifetime>,
+    // When the current connection expires, if its lifetime is limited.
+    deadlines: Option<Deadlines>,
+    states: Option<States>,
+}
+
+type BackoffFuture<B> = <<B as MakeBackoff>::Backoff as Backoff>::Future;
+
+/// A [`MakeBackoff`] that doesn't wait between connection attempts.
+///
+/// This is the default backoff of [`Reconnect`].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoBackoff;
+
+/// The state of the connection of a [`Reconnect`] service.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConnectionState {
+    /// Not connected, and not connecting.
+    Idle,
+    /// Connecting.
+    Connecting,
+    /// Connected.
+    Connected,
+    /// The connection, or the last attempt to make one, failed with the given
+    /// error.
+    ///
+    /// The service stays in this state while it waits to reconnect, and after
+    /// it has given up. Errors are only described while the state is being
+    /// watched, so a failure that happened before is described as
+    /// "connection failed".
+    Failed(String),
+}
+
+/// A [`ConnectionState`] along with when it was entered.
+///
+/// Published by the channel returned from [`Reconnect::watch_state`].
+#[derive(Clone, Debug)]
+pub struct StateChange {
+    state: ConnectionState,
+    at: Instant,
+}
+
+/// Publishes the state of the connection.
+struct States {
+    tx: watch::Sender<StateChange>,
+    // Kept so that publishing never fails, and so that more receivers can be
+    // handed out.
+    rx: watch::Receiver<StateChange>,
+}
+
+/// Limits on how long connections are used.
+#[derive(Debug)]
+struct Lifetime {
+    max_age: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    jitter: f64,
+    rng: HasherRng,
+}
+
+/// The jittered limits of a connection.
+#[derive(Debug)]
+struct Deadlines {
+    expires_at: Option<Instant>,
+    idle_timeout: Option<Duration>,
+    last_used: Instant,
 }
 
 #[derive(Debug)]
-enum State<F, S> {
+enum State<F, S, W> {
     Idle,
     Connecting(F),
     Connected(S),
+    // Waiting before the next connection attempt.
+    Backoff(W),
+    // Gave up connecting after too many failed attempts.
+    Exhausted,
 }
 
 impl<M, Target> Reconnect<M, Target>
@@ -55,6 +149,13 @@ where
             state: State::Idle,
             target,
             error: None,
+            make_backoff: NoBackoff,
+            backoff: None,
+            attempts: 0,
+            max_attempts: None,
+            lifetime: None,
+            deadlines: None,
+            states: None,
         }
     }
 
@@ -65,17 +166,175 @@ where
             state: State::Connected(init_conn),
             target,
             error: None,
+            make_backoff: NoBackoff,
+            backoff: None,
+            attempts: 0,
+            max_attempts: None,
+            lifetime: None,
+            deadlines: None,
+            states: None,
+        }
+    }
+}
+
+impl<M, Target, B> Reconnect<M, Target, B>
+where
+    M: Service<Target>,
+    B: MakeBackoff,
+{
+    /// Waits between consecutive failed connection attempts, using backoffs
+    /// made by `make_backoff`.
+    ///
+    /// A new backoff is made for every run of failed attempts, so the backoff
+    /// is reset once a connection attempt succeeds.
+    pub fn with_backoff<B2>(self, make_backoff: B2) -> Reconnect<M, Target, B2>
+    where
+        B2: MakeBackoff,
+    {
+        let state = match self.state {
+            State::Idle | State::Backoff(_) => State::Idle,
+            State::Connecting(f) => State::Connecting(f),
+            State::Connected(s) => State::Connected(s),
+            State::Exhausted => State::Exhausted,
+        };
+        Reconnect {
+            mk_service: self.mk_service,
+            state,
+            target: self.target,
+            error: self.error,
+            make_backoff,
+            backoff: None,
+            attempts: self.attempts,
+            max_attempts: self.max_attempts,
+            lifetime: self.lifetime,
+            deadlines: self.deadlines,
+            states: self.states,
+        }
+    }
+
+    /// Gives up connecting after `max` consecutive failed connection attempts.
+    ///
+    /// The error from the last attempt is returned by the next call, as with
+    /// any failed attempt. From then on, `poll_ready` fails with an
+    /// [`Exhausted`] error.
+    ///
+    /// # Panics
+    ///
+    /// If `max` is zero.
+    ///
+    /// [`Exhausted`]: crate::reconnect::error::Exhausted
+    pub fn with_max_attempts(mut self, max: usize) -> Self {
+        assert!(max > 0, "max_attempts must be positive");
+        self.max_attempts = Some(max);
+        self
+    }
+
+    /// Reconnects once the connection is older than `max_age`.
+    ///
+    /// An expired connection is dropped, and a new one is made, the next time
+    /// the `Reconnect` service is polled for readiness, so the service isn't
+    /// dropped while a request is being dispatched to it. The age of each
+    /// connection is jittered, see [`Reconnect::with_lifetime_jitter`].
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.lifetime_mut().max_age = Some(max_age);
+        self
+    }
+
+    /// Reconnects when the connection hasn't been called for `timeout`.
+    ///
+    /// Idle connections aren't dropped in the background, as that would take
+    /// a task of its own: an idle connection stays open until the `Reconnect`
+    /// service is next polled for readiness, and is then replaced with a new
+    /// one. So an unused `Reconnect` service doesn't reconnect on its own, and
+    /// requests aren't dispatched to connections that the backend, or a proxy
+    /// in between, is likely to have closed. The timeout of each connection is
+    /// jittered, see [`Reconnect::with_lifetime_jitter`].
+    pub fn with_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.lifetime_mut().idle_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the largest fraction by which the max age and idle timeout of each
+    /// connection are randomly shortened, so that many connections made at
+    /// the same time aren't all replaced at once.
+    ///
+    /// Defaults to 0.1.
+    ///
+    /// # Panics
+    ///
+    /// If `jitter` is not between 0.0 and 1.0.
+    pub fn with_lifetime_jitter(mut self, jitter: f64) -> Self {
+        assert!(
+            (0.0..=1.0).contains(&jitter),
+            "jitter must be between 0.0 and 1.0"
+        );
+        self.lifetime_mut().jitter = jitter;
+        self
+    }
+
+    /// Returns a channel that publishes every change in the state of the
+    /// connection, starting with the current state.
+    ///
+    /// Each change is timestamped, so that for instance the time since the
+    /// service was last connected can be exported. Since a [`watch`] channel
+    /// only holds the latest value, receivers that fall behind skip
+    /// intermediate states.
+    ///
+    /// [`watch`]: tokio::sync::watch
+    pub fn watch_state(&mut self) -> watch::Receiver<StateChange> {
+        if let Some(states) = self.states.as_ref() {
+            return states.rx.clone();
+        }
+
+        let state = match self.state {
+            State::Idle => ConnectionState::Idle,
+            State::Connecting(_) => ConnectionState::Connecting,
+            State::Connected(_) => ConnectionState::Connected,
+            State::Backoff(_) | State::Exhausted => {
+                ConnectionState::Failed("connection failed".to_string())
+            }
+        };
+        let (tx, rx) = watch::channel(StateChange {
+            state,
+            at: Instant::now(),
+        });
+        self.states = Some(States { tx, rx: rx.clone() });
+        rx
+    }
+
+    /// Publishes `state`, if the state is being watched.
+    fn publish(&self, state: ConnectionState) {
+        if let Some(states) = self.states.as_ref() {
+            let _ = states.tx.send(StateChange {
+                state,
+                at: Instant::now(),
+            });
         }
     }
+
+    /// Publishes that the connection failed with `error`.
+    fn publish_failure(&self, error: &crate::BoxError) {
+        self.publish(ConnectionState::Failed(error.to_string()));
+    }
+
+    fn lifetime_mut(&mut self) -> &mut Lifetime {
+        self.lifetime.get_or_insert_with(|| Lifetime {
+            max_age: None,
+            idle_timeout: None,
+            jitter: 0.1,
+            rng: HasherRng::new(),
+        })
+    }
 }
 
-impl<M, Target, S, Request> Service<Request> for Reconnect<M, Target>
+impl<M, Target, B, S, Request> Service<Request> for Reconnect<M, Target, B>
 where
     M: Service<Target, Response = S>,
     S: Service<Request>,
     M::Future: Unpin,
     crate::BoxError: From<M::Error> + From<S::Error>,
     Target: Clone,
+    B: MakeBackoff,
 {
     type Response = S::Response;
     type Error = crate::BoxError;
@@ -83,6 +342,15 @@ where
 
     fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
         loop {
+            if let (State::Connected(_), Some(lifetime)) = (&self.state, self.lifetime.as_mut()) {
+                if lifetime.expired(&mut self.deadlines) {
+                    debug!("poll_ready; connection expired");
+                    self.deadlines = None;
+                    self.state = State::Idle;
+                    self.publish(ConnectionState::Idle);
+                }
+            }
+
             match &mut self.state {
                 State::Idle => {
                     trace!("poll_ready; idle");
@@ -96,13 +364,18 @@ where
 
                     let fut = self.mk_service.make_service(self.target.clone());
                     self.state = State::Connecting(fut);
+                    self.publish(ConnectionState::Connecting);
                     continue;
                 }
                 State::Connecting(ref mut f) => {
                     trace!("poll_ready; connecting");
                     match Pin::new(f).poll(cx) {
                         Poll::Ready(Ok(service)) => {
+                            self.attempts = 0;
+                            self.backoff = None;
+                            self.deadlines = None;
                             self.state = State::Connected(service);
+                            self.publish(ConnectionState::Connected);
                         }
                         Poll::Pending => {
                             trace!("poll_ready; not ready");
@@ -110,8 +383,25 @@ where
                         }
                         Poll::Ready(Err(e)) => {
                             trace!("poll_ready; error");
-                            self.state = State::Idle;
-                            self.error = Some(e);
+                            self.attempts += 1;
+                            self.state = if Some(self.attempts) == self.max_attempts {
+                                debug!(attempts = self.attempts, "giving up connecting");
+                                State::Exhausted
+                            } else {
+                                let make_backoff = &mut self.make_backoff;
+                                let backoff = self
+                                    .backoff
+                                    .get_or_insert_with(|| make_backoff.make_backoff());
+                                State::Backoff(Box::pin(backoff.next_backoff()))
+                            };
+                            // Errors are only described when someone is watching.
+                            self.error = Some(if self.states.is_some() {
+                                let e = crate::BoxError::from(e);
+                                self.publish_failure(&e);
+                                Failure::Boxed(e)
+                            } else {
+                                Failure::Make(e)
+                            });
                             break;
                         }
                     }
@@ -127,12 +417,27 @@ where
                             trace!("poll_ready; not ready");
                             return Poll::Pending;
                         }
-                        Poll::Ready(Err(_)) => {
+                        Poll::Ready(Err(e)) => {
                             trace!("poll_ready; error");
+                            self.deadlines = None;
                             self.state = State::Idle;
+                            if self.states.is_some() {
+                                self.publish_failure(&crate::BoxError::from(e));
+                            }
                         }
                     }
                 }
+                State::Backoff(ref mut backoff) => {
+                    trace!("poll_ready; backing off");
+                    match backoff.as_mut().poll(cx) {
+                        Poll::Ready(()) => self.state = State::Idle,
+                        Poll::Pending => return Poll::Pending,
+                    }
+                }
+                State::Exhausted => {
+                    trace!("poll_ready; exhausted");
+                    return Poll::Ready(Err(error::Exhausted::new(self.attempts).into()));
+                }
             }
         }
 
@@ -149,23 +454,98 @@ where
             _ => panic!("service not ready; poll_ready must be called first"),
         };
 
+        if let Some(deadlines) = self.deadlines.as_mut() {
+            deadlines.last_used = Instant::now();
+        }
+
         let fut = service.call(request);
         ResponseFuture::new(fut)
     }
 }
 
-impl<M, Target> fmt::Debug for Reconnect<M, Target>
+impl<M, Target, B> fmt::Debug for Reconnect<M, Target, B>
 where
     M: Service<Target> + fmt::Debug,
     M::Future: fmt::Debug,
     M::Response: fmt::Debug,
     Target: fmt::Debug,
+    B: MakeBackoff + fmt::Debug,
+    BackoffFuture<B>: fmt::Debug,
 {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
         fmt.debug_struct("Reconnect")
             .field("mk_service", &self.mk_service)
             .field("state", &self.state)
             .field("target", &self.target)
+            .field("make_backoff", &self.make_backoff)
+            .field("attempts", &self.attempts)
+            .field("max_attempts", &self.max_attempts)
+            .field("lifetime", &self.lifetime)
             .finish()
     }
 }
+
+// ===== impl StateChange =====
+
+impl StateChange {
+    /// Returns the state of the connection.
+    pub fn state(&self) -> &ConnectionState {
+        &self.state
+    }
+
+    /// Returns when the connection entered this state.
+    pub fn at(&self) -> Instant {
+        self.at
+    }
+}
+
+// ===== impl Lifetime =====
+
+impl Lifetime {
+    /// Returns whether the connection with the given deadlines has expired,
+    /// starting its deadlines now if they haven't been started.
+    fn expired(&mut self, deadlines: &mut Option<Deadlines>) -> bool {
+        let now = Instant::now();
+        let deadlines = match deadlines {
+            Some(deadlines) => deadlines,
+            None => {
+                let expires_at = self.max_age.map(|max_age| now + self.jittered(max_age));
+                let idle_timeout = self.idle_timeout.map(|timeout| self.jittered(timeout));
+                deadlines.get_or_insert(Deadlines {
+                    expires_at,
+                    idle_timeout,
+                    last_used: now,
+                })
+            }
+        };
+
+        let aged = deadlines.expires_at.map_or(false, |at| now >= at);
+        let idle = deadlines
+            .idle_timeout
+            .map_or(false, |timeout| now - deadlines.last_used >= timeout);
+        aged || idle
+    }
+
+    /// Shortens `duration` by a random fraction, up to the jitter.
+    fn jittered(&mut self, duration: Duration) -> Duration {
+        duration.mul_f64(1.0 - self.jitter * self.rng.next_f64())
+    }
+}
+
+// ===== impl NoBackoff =====
+
+impl MakeBackoff for NoBackoff {
+    type Backoff = NoBackoff;
+
+    fn make_backoff(&mut self) -> Self::Backoff {
+        NoBackoff
+    }
+}
+
+impl Backoff for NoBackoff {
+    type Future = std::future::Ready<()>;
+
+    fn next_backoff(&mut self) -> Self::Future {
+        std::future::ready(())
+    }
+}
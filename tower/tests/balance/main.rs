@@ -4,9 +4,13 @@ mod support;
 
 use std::future::Future;
 use std::task::{Context, Poll};
-use tokio_test::{assert_pending, assert_ready, task};
-use tower::balance::p2c::Balance;
+use std::time::Duration;
+use tokio::time;
+use tokio_test::{assert_pending, assert_ready, assert_ready_err, assert_ready_ok, task};
+use tower::balance::p2c::{Balance, DiscoverPolicy};
 use tower::discover::Change;
+use tower::retry::backoff::ExponentialBackoffMaker;
+use tower::util::rng::HasherRng;
 use tower_service::Service;
 use tower_test::mock;
 
@@ -168,3 +172,83 @@ fn stress() {
         }
     }
 }
+
+fn backoff() -> ExponentialBackoffMaker {
+    ExponentialBackoffMaker::new(
+        Duration::from_secs(1),
+        Duration::from_secs(4),
+        0.0,
+        HasherRng::default(),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn discover_error_fails_by_default() {
+    let _t = support::trace_init();
+    let mut task = task::spawn(());
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Result<_, &'static str>>();
+    let mut balance = Balance::<_, Req>::new(support::IntoStream::new(rx));
+
+    let (svc, mut handle) = mock::pair::<Req, Req>();
+    handle.allow(1);
+    tx.send(Ok(Change::Insert(0, Mock(svc)))).unwrap();
+    assert_ready_ok!(task.enter(|cx, _| balance.poll_ready(cx)));
+
+    tx.send(Err("doom")).unwrap();
+    let error = assert_ready_err!(task.enter(|cx, _| balance.poll_ready(cx)));
+    assert_eq!(error.to_string(), "load balancer discovery error: doom",);
+}
+
+#[tokio::test(start_paused = true)]
+async fn discover_error_keeps_endpoints() {
+    let _t = support::trace_init();
+    let mut task = task::spawn(());
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Result<_, &'static str>>();
+    let mut balance = Balance::<_, Req>::new(support::IntoStream::new(rx))
+        .with_discover_policy(DiscoverPolicy::retry(backoff()));
+
+    let (svc0, mut handle0) = mock::pair::<Req, Req>();
+    handle0.allow(1);
+    tx.send(Ok(Change::Insert(0, Mock(svc0)))).unwrap();
+    tx.send(Err("doom")).unwrap();
+
+    // The balancer keeps serving with the endpoint it already knows about.
+    assert_ready_ok!(task.enter(|cx, _| balance.poll_ready(cx)));
+    assert_eq!(balance.len(), 1);
+
+    // Discovery is not polled again until the backoff elapses.
+    let (svc1, mut handle1) = mock::pair::<Req, Req>();
+    handle1.allow(1);
+    tx.send(Ok(Change::Insert(1, Mock(svc1)))).unwrap();
+    assert_ready_ok!(task.enter(|cx, _| balance.poll_ready(cx)));
+    assert_eq!(balance.len(), 1);
+
+    time::sleep(Duration::from_secs(1)).await;
+    assert!(task.is_woken());
+    assert_ready_ok!(task.enter(|cx, _| balance.poll_ready(cx)));
+    assert_eq!(balance.len(), 2);
+}
+
+#[tokio::test(start_paused = true)]
+async fn discover_error_fails_after_timeout() {
+    let _t = support::trace_init();
+    let mut task = task::spawn(());
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel::<Result<Change<usize, Mock>, _>>();
+    let mut balance = Balance::<_, Req>::new(support::IntoStream::new(rx))
+        .with_discover_policy(DiscoverPolicy::retry(backoff()).fail_after(Duration::from_secs(5)));
+
+    // Discovery keeps failing, backing off for 1s, 2s and then 4s.
+    for delay in &[1, 2] {
+        tx.send(Err("doom")).unwrap();
+        assert_pending!(task.enter(|cx, _| balance.poll_ready(cx)));
+        time::sleep(Duration::from_secs(*delay)).await;
+    }
+
+    // Once the errors have lasted long enough, the balancer fails.
+    tx.send(Err("doom")).unwrap();
+    assert_pending!(task.enter(|cx, _| balance.poll_ready(cx)));
+    time::sleep(Duration::from_secs(4)).await;
+    tx.send(Err("doom")).unwrap();
+    assert_ready_err!(task.enter(|cx, _| balance.poll_ready(cx)));
+}
@@ -437,6 +437,42 @@ async fn doesnt_leak_permits() {
     assert_ready_ok!(ready3.poll());
 }
 
+#[cfg(feature = "load")]
+#[tokio::test(flavor = "current_thread")]
+async fn reports_queue_depth_and_inner_load() {
+    use tower::load::{Constant, Load};
+
+    let _t = support::trace_init();
+
+    let (service, mut handle) = mock::pair::<_, ()>();
+    handle.allow(0);
+
+    let (mut service, worker) = Buffer::pair_with_load(Constant::new(service, 3.0), 2);
+    let mut worker = task::spawn(worker);
+    assert_eq!(service.load().queued(), 0);
+    assert_eq!(service.load().inner(), 3.0);
+
+    assert_ready_ok!(task::spawn(service.ready()).poll());
+    let mut response1 = task::spawn(service.call(()));
+    assert_ready_ok!(task::spawn(service.ready()).poll());
+    let mut response2 = task::spawn(service.call(()));
+    assert_eq!(service.load().queued(), 2);
+
+    // The worker takes the first request off the queue, but can't dispatch it yet.
+    assert_pending!(worker.poll());
+    assert_eq!(service.load().queued(), 1);
+
+    handle.allow(2);
+    assert_pending!(worker.poll());
+    assert_eq!(service.load().queued(), 0);
+
+    handle.next_request().await.unwrap().1.send_response(());
+    handle.next_request().await.unwrap().1.send_response(());
+    assert_pending!(worker.poll());
+    assert_ready_ok!(response1.poll());
+    assert_ready_ok!(response2.poll());
+}
+
 type Handle = mock::Handle<&'static str, &'static str>;
 type MockBuffer = Buffer<&'static str, mock::future::ResponseFuture<&'static str>>;
 
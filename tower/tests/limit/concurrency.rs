@@ -215,3 +215,55 @@ async fn multi_waiters() {
 
     assert!(s3.is_woken());
 }
+
+#[cfg(feature = "load")]
+#[tokio::test(flavor = "current_thread")]
+async fn reports_permits_in_use_and_inner_load() {
+    use tower::limit::{concurrency::PermitsInUse, ConcurrencyLimit};
+    use tower::load::{Constant, Load};
+
+    let _t = support::trace_init();
+    let (mut service, mut handle) =
+        mock::spawn_with(|svc| PermitsInUse::new(ConcurrencyLimit::new(Constant::new(svc, 7), 2)));
+    assert_eq!(service.get_ref().load().in_use(), 0);
+    assert_eq!(*service.get_ref().load().inner(), 7);
+
+    assert_ready_ok!(service.poll_ready());
+    assert_eq!(service.get_ref().load().in_use(), 1);
+    let r1 = service.call("hello 1");
+
+    assert_ready_ok!(service.poll_ready());
+    let r2 = service.call("hello 2");
+    assert_eq!(service.get_ref().load().in_use(), 2);
+
+    assert_request_eq!(handle, "hello 1").send_response("world 1");
+    assert_eq!(r1.await.unwrap(), "world 1");
+    assert_eq!(service.get_ref().load().in_use(), 1);
+
+    assert_request_eq!(handle, "hello 2").send_response("world 2");
+    assert_eq!(r2.await.unwrap(), "world 2");
+    assert_eq!(service.get_ref().load().in_use(), 0);
+}
+
+#[cfg(feature = "load")]
+#[tokio::test(flavor = "current_thread")]
+async fn global_limit_reports_shared_permits_in_use() {
+    use tower::limit::{concurrency::PermitsInUse, GlobalConcurrencyLimitLayer};
+    use tower::load::{Constant, Load};
+    use tower::Layer;
+
+    let _t = support::trace_init();
+    let layer = GlobalConcurrencyLimitLayer::new(2);
+    let (mut first, _handle) = mock::spawn_with(|svc: mock::Mock<(), ()>| {
+        PermitsInUse::new(layer.layer(Constant::new(svc, 0)))
+    });
+    assert_ready_ok!(first.poll_ready());
+
+    // A service created while a permit is held still knows the limit, and
+    // counts the permits held by the services it shares the semaphore with.
+    let (second, _handle) = mock::spawn_with(|svc: mock::Mock<(), ()>| {
+        PermitsInUse::new(layer.layer(Constant::new(svc, 0)))
+    });
+    assert_eq!(first.get_ref().load().in_use(), 1);
+    assert_eq!(second.get_ref().load().in_use(), 1);
+}
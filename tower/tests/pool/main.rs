@@ -0,0 +1,157 @@
+#![cfg(feature = "pool")]
+#[path = "../support.rs"]
+mod support;
+
+use std::time::Duration;
+use tokio::time;
+use tokio_test::{assert_pending, assert_ready, assert_ready_ok};
+use tower::pool::Pool;
+use tower_test::mock;
+
+type Req = &'static str;
+type Mock = mock::Mock<Req, Req>;
+type MakeHandle = mock::Handle<(), Mock>;
+
+/// Responds to the next request to make a member with a new member, that is
+/// ready for `allow` requests.
+fn make_member(handle: &mut MakeHandle, allow: u64) -> mock::Handle<Req, Req> {
+    let (svc, mut svc_handle) = mock::pair::<Req, Req>();
+    svc_handle.allow(allow);
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_response(svc);
+    svc_handle
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn grows_up_to_max() {
+    let _t = support::trace_init();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let mut pool = mock::Spawn::new(Pool::new(make, ()).with_size(0, 2));
+
+    // A member is made when none is ready.
+    assert_pending!(pool.poll_ready());
+    let mut handle0 = make_member(&mut handle, 1);
+    assert_ready_ok!(pool.poll_ready());
+    drop(pool.call("a"));
+    assert_eq!(assert_ready!(handle0.poll_request()).unwrap().0, "a");
+
+    // The first member is busy.
+    assert_pending!(pool.poll_ready());
+    let mut handle1 = make_member(&mut handle, 1);
+    assert_ready_ok!(pool.poll_ready());
+    drop(pool.call("b"));
+    assert_eq!(assert_ready!(handle1.poll_request()).unwrap().0, "b");
+
+    // Both members are busy, and the pool is full.
+    assert_pending!(pool.poll_ready());
+    assert_pending!(handle.poll_request());
+    let stats = pool.get_ref().stats();
+    assert_eq!((stats.len(), stats.ready(), stats.created()), (2, 0, 2));
+
+    handle0.allow(1);
+    assert_ready_ok!(pool.poll_ready());
+    drop(pool.call("c"));
+    assert_eq!(assert_ready!(handle0.poll_request()).unwrap().0, "c");
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn replaces_failed_members() {
+    let _t = support::trace_init();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let mut pool = mock::Spawn::new(Pool::new(make, ()).with_size(0, 1));
+
+    // Failing to make the first member fails the next request, rather than the pool.
+    assert_pending!(pool.poll_ready());
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_error("refused");
+    assert_ready_ok!(pool.poll_ready());
+    let err = pool.call("a").await.unwrap_err();
+    assert_eq!(err.to_string(), "refused");
+
+    // The pool tries again when it is polled again.
+    assert_pending!(pool.poll_ready());
+    let mut handle0 = make_member(&mut handle, 1);
+    assert_ready_ok!(pool.poll_ready());
+
+    handle0.send_error("lost");
+    assert_pending!(pool.poll_ready());
+    let _handle1 = make_member(&mut handle, 1);
+    assert_ready_ok!(pool.poll_ready());
+    let stats = pool.get_ref().stats();
+    assert_eq!((stats.len(), stats.created(), stats.failed()), (1, 2, 1));
+}
+
+#[cfg(feature = "util")]
+#[tokio::test(flavor = "current_thread")]
+async fn stops_making_members_after_a_failure() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let _t = support::trace_init();
+
+    // Makes one member, and then fails right away.
+    let (svc, mut handle0) = mock::pair::<Req, Req>();
+    handle0.allow(1);
+    let mut svc = Some(svc);
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let make = tower::service_fn({
+        let attempts = attempts.clone();
+        move |()| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            std::future::ready(svc.take().ok_or("refused"))
+        }
+    });
+    let mut pool = mock::Spawn::new(Pool::new(make, ()).with_size(0, 2));
+
+    assert_ready_ok!(pool.poll_ready());
+    drop(pool.call("a"));
+
+    // The only member is busy, and each poll makes a single attempt to make another one.
+    assert_pending!(pool.poll_ready());
+    assert_eq!(attempts.load(Ordering::SeqCst), 2);
+    assert_pending!(pool.poll_ready());
+    assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+    handle0.allow(1);
+    assert!(pool.is_woken());
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(pool.get_ref().len(), 1);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn evicts_idle_members_down_to_min() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let pool = Pool::new(make, ())
+        .with_size(1, 2)
+        .with_idle_timeout(Duration::from_secs(10));
+    let mut pool = mock::Spawn::new(pool);
+
+    assert_pending!(pool.poll_ready());
+    let mut handle0 = make_member(&mut handle, 1);
+    assert_ready_ok!(pool.poll_ready());
+    drop(pool.call("a"));
+    assert_pending!(pool.poll_ready());
+    let _handle1 = make_member(&mut handle, 0);
+    handle0.allow(1);
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(pool.get_ref().len(), 2);
+
+    // Only the member that was used recently is kept.
+    time::sleep(Duration::from_secs(5)).await;
+    drop(pool.call("b"));
+    handle0.allow(1);
+    time::sleep(Duration::from_secs(5)).await;
+    assert_ready_ok!(pool.poll_ready());
+    let stats = pool.get_ref().stats();
+    assert_eq!((stats.len(), stats.evicted()), (1, 1));
+
+    // The pool doesn't shrink below its minimum size.
+    time::sleep(Duration::from_secs(20)).await;
+    assert_ready_ok!(pool.poll_ready());
+    assert_eq!(pool.get_ref().len(), 1);
+}
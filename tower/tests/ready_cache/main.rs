@@ -3,8 +3,10 @@
 mod support;
 
 use std::pin::Pin;
+use std::time::Duration;
+use tokio::time;
 use tokio_test::{assert_pending, assert_ready, task};
-use tower::ready_cache::{error, ReadyCache};
+use tower::ready_cache::{cache::State, error, ReadyCache};
 use tower_test::mock;
 
 type Req = &'static str;
@@ -221,3 +223,157 @@ async fn cancelation_observed() {
     }
     Ready(cache).await.unwrap();
 }
+
+#[test]
+fn iter_skips_evicted() {
+    let _t = support::trace_init();
+
+    let mut task = task::spawn(());
+    let mut cache = ReadyCache::<usize, Mock, Req>::default();
+
+    let (service0, mut handle0) = mock::pair::<Req, Req>();
+    handle0.allow(1);
+    cache.push(0, service0);
+
+    let (service1, mut handle1) = mock::pair::<Req, Req>();
+    handle1.allow(0);
+    cache.push(1, service1);
+
+    let (service2, mut handle2) = mock::pair::<Req, Req>();
+    handle2.allow(0);
+    cache.push(2, service2);
+
+    assert_pending!(task.enter(|cx, _| cache.poll_pending(cx)));
+    cache.evict(&2);
+
+    let states = cache
+        .iter()
+        .map(|(key, _, state)| (*key, state))
+        .collect::<Vec<_>>();
+    assert_eq!(states, vec![(0, State::Ready), (1, State::Pending)]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn max_len_evicts_least_recently_used() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let mut task = task::spawn(());
+    let mut cache = ReadyCache::<usize, Mock, Req>::default().with_max_len(2);
+
+    let (service0, mut handle0) = mock::pair::<Req, Req>();
+    handle0.allow(2);
+    cache.push(0, service0);
+    time::advance(Duration::from_secs(1)).await;
+
+    let (service1, mut handle1) = mock::pair::<Req, Req>();
+    handle1.allow(1);
+    cache.push(1, service1);
+    time::advance(Duration::from_secs(1)).await;
+
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+    assert!(task.enter(|cx, _| cache.check_ready(cx, &0)).unwrap());
+    drop(cache.call_ready(&0, "hello"));
+    time::advance(Duration::from_secs(1)).await;
+
+    // Service 1 was used least recently.
+    let (service2, mut handle2) = mock::pair::<Req, Req>();
+    handle2.allow(1);
+    cache.push(2, service2);
+    assert_eq!(cache.len(), 2);
+    assert!(cache.pending_contains(&0));
+    assert!(cache.pending_contains(&2));
+
+    // Replacing a service doesn't evict another one.
+    let (service2, mut handle2) = mock::pair::<Req, Req>();
+    handle2.allow(1);
+    cache.push(2, service2);
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+    let mut keys = cache.iter_ready().map(|(key, _)| *key).collect::<Vec<_>>();
+    keys.sort_unstable();
+    assert_eq!(keys, vec![0, 2]);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn idle_timeout_drops_unused_services() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let mut task = task::spawn(());
+    let mut cache =
+        ReadyCache::<usize, Mock, Req>::default().with_idle_timeout(Duration::from_secs(10));
+
+    let (service0, mut handle0) = mock::pair::<Req, Req>();
+    handle0.allow(2);
+    cache.push(0, service0);
+
+    let (service1, mut handle1) = mock::pair::<Req, Req>();
+    handle1.allow(1);
+    cache.push(1, service1);
+
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+    time::advance(Duration::from_secs(6)).await;
+    assert!(task.enter(|cx, _| cache.check_ready(cx, &0)).unwrap());
+    drop(cache.call_ready(&0, "hello"));
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+    assert_eq!(cache.ready_len(), 2);
+
+    time::advance(Duration::from_secs(6)).await;
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+    assert_eq!(cache.ready_len(), 1);
+    assert!(cache.get_ready(&0).is_some());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn pending_timeout_fails_service() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let mut task = task::spawn(());
+    let mut cache =
+        ReadyCache::<usize, Mock, Req>::default().with_pending_timeout(Duration::from_secs(5));
+
+    let (service0, mut handle0) = mock::pair::<Req, Req>();
+    handle0.allow(0);
+    cache.push(0, service0);
+
+    let (service1, mut handle1) = mock::pair::<Req, Req>();
+    handle1.allow(1);
+    cache.push(1, service1);
+
+    assert_pending!(task.enter(|cx, _| cache.poll_pending(cx)));
+    time::sleep(Duration::from_secs(5)).await;
+    assert!(task.is_woken());
+
+    let failed = assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap_err();
+    assert_eq!(failed.0, 0);
+    assert!(failed.1.is::<error::PendingTimeout>());
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+    assert_eq!(cache.len(), 1);
+    assert!(cache.get_ready(&1).is_some());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn pending_timeout_spares_called_services() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let mut task = task::spawn(());
+    let mut cache =
+        ReadyCache::<usize, Mock, Req>::default().with_pending_timeout(Duration::from_secs(5));
+
+    let (service0, mut handle0) = mock::pair::<Req, Req>();
+    handle0.allow(1);
+    cache.push(0, service0);
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+
+    // The service is busy after it is called, for longer than the timeout.
+    drop(cache.call_ready(&0, "a"));
+    assert_pending!(task.enter(|cx, _| cache.poll_pending(cx)));
+    time::sleep(Duration::from_secs(10)).await;
+    assert_pending!(task.enter(|cx, _| cache.poll_pending(cx)));
+
+    handle0.allow(1);
+    assert_ready!(task.enter(|cx, _| cache.poll_pending(cx))).unwrap();
+    assert!(cache.get_ready(&0).is_some());
+}
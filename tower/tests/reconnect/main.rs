@@ -0,0 +1,226 @@
+#![cfg(feature = "reconnect")]
+#[path = "../support.rs"]
+mod support;
+
+use std::time::Duration;
+use tokio::time;
+use tokio_test::{assert_pending, assert_ready, assert_ready_err, assert_ready_ok};
+use tower::reconnect::{error::Exhausted, ConnectionState, Reconnect};
+use tower::retry::backoff::ExponentialBackoffMaker;
+use tower::util::rng::HasherRng;
+use tower_test::mock;
+
+type Req = &'static str;
+type Mock = mock::Mock<Req, Req>;
+
+fn backoff() -> ExponentialBackoffMaker {
+    let min = Duration::from_secs(1);
+    let max = Duration::from_secs(10);
+    ExponentialBackoffMaker::new(min, max, 0.0, HasherRng::default()).unwrap()
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn backs_off_and_gives_up() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let reconnect = Reconnect::new(make, ())
+        .with_backoff(backoff())
+        .with_max_attempts(3);
+    let mut reconnect = mock::Spawn::new(reconnect);
+
+    // The first attempt is made right away.
+    assert_pending!(reconnect.poll_ready());
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_error("refused");
+    assert_ready_ok!(reconnect.poll_ready());
+    let err = reconnect.call("hello").await.unwrap_err();
+    assert_eq!(err.to_string(), "refused");
+
+    // The next attempts wait for 1 and then 2 seconds.
+    for delay in [1, 2] {
+        assert_pending!(reconnect.poll_ready());
+        assert_pending!(handle.poll_request());
+        time::sleep(Duration::from_millis(delay * 1000 - 1)).await;
+        assert_pending!(reconnect.poll_ready());
+        assert_pending!(handle.poll_request());
+        time::sleep(Duration::from_millis(1)).await;
+        assert_pending!(reconnect.poll_ready());
+        let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+        rsp.send_error("refused");
+        assert_ready_ok!(reconnect.poll_ready());
+        assert!(reconnect.call("hello").await.is_err());
+    }
+
+    let err = assert_ready_err!(reconnect.poll_ready());
+    let err = err.downcast::<Exhausted>().unwrap();
+    assert_eq!(err.attempts(), 3);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn resets_backoff_after_connecting() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let reconnect = Reconnect::new(make, ()).with_backoff(backoff());
+    let mut reconnect = mock::Spawn::new(reconnect);
+
+    assert_pending!(reconnect.poll_ready());
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_error("refused");
+    assert_ready_ok!(reconnect.poll_ready());
+    assert!(reconnect.call("hello").await.is_err());
+
+    // Connect after backing off for a second.
+    assert_pending!(reconnect.poll_ready());
+    time::sleep(Duration::from_secs(1)).await;
+    assert_pending!(reconnect.poll_ready());
+    let (svc, mut svc_handle) = mock::pair::<Req, Req>();
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_response(svc);
+    assert_ready_ok!(reconnect.poll_ready());
+
+    // When the connection fails, the next failed attempt backs off from the
+    // start again.
+    svc_handle.send_error("lost");
+    assert_pending!(reconnect.poll_ready());
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_error("refused");
+    assert_ready_ok!(reconnect.poll_ready());
+    assert!(reconnect.call("hello").await.is_err());
+
+    assert_pending!(reconnect.poll_ready());
+    time::sleep(Duration::from_secs(1)).await;
+    assert_pending!(reconnect.poll_ready());
+    assert_ready!(handle.poll_request()).unwrap();
+}
+
+/// Polls `reconnect` until it has connected to a new service.
+fn connect(
+    reconnect: &mut mock::Spawn<Reconnect<mock::Mock<(), Mock>, ()>>,
+    handle: &mut mock::Handle<(), Mock>,
+) -> mock::Handle<Req, Req> {
+    assert_pending!(reconnect.poll_ready());
+    let (svc, svc_handle) = mock::pair::<Req, Req>();
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_response(svc);
+    assert_ready_ok!(reconnect.poll_ready());
+    svc_handle
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn reconnects_after_max_age() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let reconnect = Reconnect::new(make, ())
+        .with_max_age(Duration::from_secs(10))
+        .with_lifetime_jitter(0.0);
+    let mut reconnect = mock::Spawn::new(reconnect);
+    let _svc_handle = connect(&mut reconnect, &mut handle);
+
+    // The connection is kept while it's in use.
+    time::sleep(Duration::from_secs(9)).await;
+    drop(reconnect.call("hello"));
+    assert_ready_ok!(reconnect.poll_ready());
+    assert_pending!(handle.poll_request());
+
+    time::sleep(Duration::from_secs(1)).await;
+    let _svc_handle = connect(&mut reconnect, &mut handle);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn replaces_idle_connection_when_polled() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let reconnect = Reconnect::new(make, ())
+        .with_idle_timeout(Duration::from_secs(10))
+        .with_lifetime_jitter(0.0);
+    let mut reconnect = mock::Spawn::new(reconnect);
+    let mut svc_handle = connect(&mut reconnect, &mut handle);
+
+    // Each call keeps the connection alive for another 10 seconds.
+    for _ in 0..3 {
+        time::sleep(Duration::from_secs(9)).await;
+        assert_ready_ok!(reconnect.poll_ready());
+        drop(reconnect.call("hello"));
+        assert_ready!(svc_handle.poll_request()).unwrap();
+    }
+    assert_pending!(handle.poll_request());
+
+    // Nothing happens once the connection is idle, until the service is
+    // polled again.
+    time::sleep(Duration::from_secs(10)).await;
+    assert!(!reconnect.is_woken());
+    assert_pending!(handle.poll_request());
+    assert_pending!(svc_handle.poll_request());
+
+    let _svc_handle = connect(&mut reconnect, &mut handle);
+    assert!(assert_ready!(svc_handle.poll_request()).is_none());
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn publishes_state_changes() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let mut reconnect = Reconnect::new(make, ());
+    let states = reconnect.watch_state();
+    let state = || states.borrow().state().clone();
+    let mut reconnect = mock::Spawn::new(reconnect);
+    assert_eq!(state(), ConnectionState::Idle);
+
+    assert_pending!(reconnect.poll_ready());
+    assert_eq!(state(), ConnectionState::Connecting);
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_error("refused");
+    assert_ready_ok!(reconnect.poll_ready());
+    assert_eq!(state(), ConnectionState::Failed("refused".to_string()));
+    assert!(reconnect.call("hello").await.is_err());
+
+    time::sleep(Duration::from_secs(1)).await;
+    let mut svc_handle = connect(&mut reconnect, &mut handle);
+    assert_eq!(state(), ConnectionState::Connected);
+    assert_eq!(states.borrow().at(), time::Instant::now());
+
+    // A lost connection is published before reconnecting.
+    handle.allow(0);
+    svc_handle.send_error("lost");
+    assert_pending!(reconnect.poll_ready());
+    assert_eq!(state(), ConnectionState::Failed("lost".to_string()));
+
+    handle.allow(1);
+    assert_pending!(reconnect.poll_ready());
+    assert_eq!(state(), ConnectionState::Connecting);
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn publishes_failure_when_watched_late() {
+    let _t = support::trace_init();
+    time::pause();
+
+    let (make, mut handle) = mock::pair::<(), Mock>();
+    let reconnect = Reconnect::new(make, ()).with_backoff(backoff());
+    let mut reconnect = mock::Spawn::new(reconnect);
+
+    assert_pending!(reconnect.poll_ready());
+    let (_, rsp) = assert_ready!(handle.poll_request()).unwrap();
+    rsp.send_error("refused");
+    assert_ready_ok!(reconnect.poll_ready());
+    assert!(reconnect.call("hello").await.is_err());
+
+    // Watching while backing off publishes the failure, though the error that
+    // caused it wasn't described, since nobody was watching.
+    assert_pending!(reconnect.poll_ready());
+    let states = reconnect.get_mut().watch_state();
+    assert_eq!(
+        *states.borrow().state(),
+        ConnectionState::Failed("connection failed".to_string())
+    );
+}